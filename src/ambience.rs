@@ -0,0 +1,55 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioApp, AudioChannel, AudioControl, AudioInstance};
+
+use crate::{
+    extras::AudioZone,
+    music::{Crossfader, CROSSFADE_TIME},
+    settings::Settings,
+    zones::{InsideZones, ZoneTracking, ZoneTrackingPlugin},
+};
+
+pub(crate) struct AmbiencePlugin;
+
+impl Plugin for AmbiencePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<Ambience>()
+            .add_plugin(ZoneTrackingPlugin::<AudioZone>::default())
+            .init_resource::<ZoneAmbience>()
+            .add_system(play_ambience.after(ZoneTracking));
+    }
+}
+
+#[derive(Resource)]
+struct Ambience;
+
+/// The last entered zone the player is in is heard
+#[derive(Resource, Default)]
+struct ZoneAmbience {
+    tracks: Crossfader<AudioZone>,
+}
+
+fn play_ambience(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    channel: Res<AudioChannel<Ambience>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut ambience: ResMut<ZoneAmbience>,
+    inside: Res<InsideZones<AudioZone>>,
+    zones: Query<&AudioZone>,
+) {
+    let zone = inside.last().and_then(|z| zones.get(z).ok());
+    ambience.tracks.update(
+        zone,
+        |zone| {
+            channel
+                .play(asset_server.load(zone.ambience.as_str()))
+                .looped()
+                .with_volume(0.0)
+                .handle()
+        },
+        time.delta_seconds() / CROSSFADE_TIME,
+        |zone| zone.volume as f64 * settings.mixer.sfx(),
+        &mut audio_instances,
+    );
+}
@@ -1,20 +1,22 @@
-use bevy::prelude::{
-    info, warn, AssetServer, EventWriter, Handle, IntoSystemAppConfig, IntoSystemConfig, OnExit,
-    OnUpdate, Plugin, Res, ResMut, Resource,
+use bevy::{ecs::system::SystemParam, prelude::*, utils::HashMap};
+use bevy_kira_audio::{
+    AudioApp, AudioChannel, AudioControl, AudioInstance, AudioSource, PlaybackState,
 };
-use bevy_kira_audio::{AudioApp, AudioChannel, AudioControl, AudioSource};
+use bevy_rapier3d::prelude::{QueryFilter, RapierContext};
 
-use crate::game::GameState;
-use crate::hud::SubtitleTrigger;
-use crate::AppState;
+use crate::dialogue::DialogueQueue;
+pub use crate::extras::VoiceChannel;
+use crate::settings::Settings;
+use crate::{AppState, PlayerHead};
 
 pub(crate) struct AudioPlugin;
 
+/// Footsteps and other sound effects
 #[derive(Resource)]
-struct BgMusic;
+pub struct Sfx;
 
 #[derive(Resource)]
-pub struct SpawnRoomSpeaker;
+struct SpawnRoomSpeaker;
 
 #[derive(Resource)]
 struct ProtagonistVoice;
@@ -23,124 +25,164 @@ struct ProtagonistVoice;
 struct FirstRoomSpeaker;
 
 impl Plugin for AudioPlugin {
-    fn build(&self, app: &mut bevy::prelude::App) {
+    fn build(&self, app: &mut App) {
         app.add_plugin(bevy_kira_audio::AudioPlugin)
-            .add_audio_channel::<BgMusic>()
+            .add_audio_channel::<Sfx>()
             .add_audio_channel::<SpawnRoomSpeaker>()
-            .add_system(dialogue.in_set(OnUpdate(AppState::InGame)))
-            .add_system(stop_all_dialogue.in_schedule(OnExit(AppState::InGame)))
-            .add_startup_system(start_music)
-            .insert_resource(DialoguePlaying::None);
-        // .add_system(adjust_volume_on_distance);
+            .add_audio_channel::<FirstRoomSpeaker>()
+            .add_audio_channel::<ProtagonistVoice>()
+            .add_system(adjust_volume_on_distance.in_set(OnUpdate(AppState::InGame)))
+            .add_system(stop_all_dialogue.in_schedule(OnExit(AppState::InGame)));
     }
 }
 
-// enter the test chamber
-pub static AUDIO_FILES: &[&str] = &[
-    "01-doc1.ogg",
-    "02-bvj.ogg",
-    "03-doc1.ogg",
-    "04-doc1.ogg",
-    "05-bvj.ogg",
-    "06-doc2.ogg",
-    "07-doc1.ogg",
-    "08-bvj.ogg",
-    "09-doc1.ogg",
-    "10-doc2.ogg",
-    "11-doc1.ogg",
-    "12-bvj.ogg",
-    "13-doc1.ogg",
-    "14-doc2.ogg",
-    "15-doc1.ogg",
-    "16-bvj.ogg",
-    "17-doc2.ogg",
-    "18-doc2.ogg",
-    "19-doc1.ogg",
-];
-
-fn start_music(asset_server: Res<AssetServer>, audio: Res<AudioChannel<BgMusic>>) {
-    for f in AUDIO_FILES {
-        let _h: Handle<AudioSource> = asset_server.load(String::from("sounds/dialogues/") + *f);
-    }
+/// Lab speaker the voices of the channel come from, `AudioSpeaker_<channel>` in the level
+#[derive(Component)]
+pub struct LabSpeaker(pub VoiceChannel);
 
-    audio
-        .play(asset_server.load("sounds/bvj-3-space-lab.ogg"))
-        .looped();
-    audio.set_volume(0.2);
+macro_rules! on_voice_channel {
+    ($voices:expr, $channel:expr, |$audio:ident| $body:expr) => {
+        match $channel {
+            VoiceChannel::SpawnRoom => {
+                let $audio = &*$voices.spawn_room;
+                $body
+            }
+            VoiceChannel::FirstRoom => {
+                let $audio = &*$voices.first_room;
+                $body
+            }
+            VoiceChannel::Protagonist => {
+                let $audio = &*$voices.protagonist;
+                $body
+            }
+        }
+    };
 }
 
-#[derive(Resource)]
-enum DialoguePlaying {
-    None,
-    StartedButNotPlaying(usize),
-    Playing(usize),
+#[derive(SystemParam)]
+pub struct VoiceChannels<'w> {
+    spawn_room: Res<'w, AudioChannel<SpawnRoomSpeaker>>,
+    first_room: Res<'w, AudioChannel<FirstRoomSpeaker>>,
+    protagonist: Res<'w, AudioChannel<ProtagonistVoice>>,
+    settings: Res<'w, Settings>,
 }
 
-static SPEED: f64 = 1.5;
+impl VoiceChannels<'_> {
+    pub const ALL: [VoiceChannel; 3] = [
+        VoiceChannel::SpawnRoom,
+        VoiceChannel::FirstRoom,
+        VoiceChannel::Protagonist,
+    ];
+
+    pub fn play(&self, channel: VoiceChannel, line: Handle<AudioSource>) -> Handle<AudioInstance> {
+        on_voice_channel!(self, channel, |audio| audio
+            .play(line)
+            .with_playback_rate(self.settings.mixer.dialogue_speed as f64)
+            .handle())
+    }
 
-pub static SUBTITLES: &str = include_str!("../assets/text/subtitles.txt");
+    pub fn state(&self, channel: VoiceChannel, instance: &Handle<AudioInstance>) -> PlaybackState {
+        on_voice_channel!(self, channel, |audio| audio.state(instance))
+    }
 
-fn dialogue(
-    asset_server: Res<AssetServer>,
-    mut playing: ResMut<DialoguePlaying>,
-    audio_channel: Res<AudioChannel<SpawnRoomSpeaker>>,
-    mut events: EventWriter<SubtitleTrigger>,
-    mut game_state: ResMut<GameState>,
-) {
-    audio_channel.set_playback_rate(SPEED);
-    let play_dialogue_file = |n: usize| String::from("sounds/dialogues/") + AUDIO_FILES[n];
-    let on_spawn = 2;
-    let in_room = 4;
-    let after_first_laser = 11;
-
-    let play_first_phase_dialog =
-        |n: usize| audio_channel.play(asset_server.load(play_dialogue_file(n)));
-    let phase_end = match *game_state {
-        GameState::JustSpawned => on_spawn,
-        GameState::InTestingRoom | GameState::TurnOnLaser1 => in_room,
-        GameState::Laser1EffectDiscussion | GameState::TurnOnLaser2 => after_first_laser,
-        GameState::Escape => 15
-    };
+    /// Someone is talking on any of the channels
+    pub fn is_playing(&self) -> bool {
+        Self::ALL
+            .into_iter()
+            .any(|channel| on_voice_channel!(self, channel, |audio| audio.is_playing_sound()))
+    }
 
-    let new_state = match *playing {
-        DialoguePlaying::None => {
-            info!("Started");
-            play_first_phase_dialog(0);
-            DialoguePlaying::StartedButNotPlaying(0)
-        }
-        DialoguePlaying::StartedButNotPlaying(n) => {
-            let subtit = SUBTITLES.lines().nth(n).unwrap();
-            events.send(SubtitleTrigger(subtit.to_string()));
-            if audio_channel.is_playing_sound() {
-                DialoguePlaying::Playing(n)
-            } else {
-                DialoguePlaying::StartedButNotPlaying(n)
-            }
-        }
-        DialoguePlaying::Playing(n) => {
-            if !audio_channel.is_playing_sound() {
-                if n == in_room && matches!(*game_state, GameState::InTestingRoom) {
-                    *game_state = GameState::TurnOnLaser1;
-                }
-                if n == after_first_laser && *game_state == GameState::Laser1EffectDiscussion {
-                    *game_state = GameState::TurnOnLaser2;
-                }
-            }
-            if audio_channel.is_playing_sound() {
-                DialoguePlaying::Playing(n)
-            } else if n < phase_end {
-                info!("Continuing to {n}+1");
-                play_first_phase_dialog(n + 1);
-                DialoguePlaying::StartedButNotPlaying(n + 1)
-            } else {
-                events.send(SubtitleTrigger(String::new()));
-                DialoguePlaying::Playing(n)
-            }
+    fn set_spatial(&self, channel: VoiceChannel, volume: f64, panning: f64) {
+        on_voice_channel!(self, channel, |audio| {
+            audio.set_volume(volume * self.settings.mixer.voice());
+            audio.set_panning(panning);
+        })
+    }
+
+    fn stop(&self) {
+        for channel in Self::ALL {
+            on_voice_channel!(self, channel, |audio| audio.stop());
         }
-    };
-    *playing = new_state;
+    }
+}
+
+/// Voices fade out until this distance from the speaker
+static MAX_SPEAKER_DISTANCE: f32 = 20.0;
+/// So the story can still be followed from anywhere in the lab
+static MIN_SPEAKER_VOLUME: f32 = 0.15;
+/// Volume left of a speaker behind a wall
+static OCCLUDED_VOLUME: f32 = 0.35;
+/// Speakers hang on walls, so the ray stops short of them
+static SPEAKER_CLEARANCE: f32 = 0.3;
+/// Seconds for a speaker to get muffled or clear when a wall gets in the way
+static OCCLUSION_TIME: f32 = 0.2;
+
+/// Fades out until `max_distance`
+pub fn distance_volume(sound_path: Vec3, max_distance: f32) -> f32 {
+    (1.0 - sound_path.length() / max_distance)
+        .clamp(0.0, 1.0)
+        .powi(2)
+}
+
+/// 0 is left, 1 is right of the listener
+pub fn panning(head: &GlobalTransform, sound_path: Vec3) -> f32 {
+    if sound_path.length() > f32::EPSILON {
+        (head.right().angle_between(sound_path).cos() + 1.0) / 2.0
+    } else {
+        0.5
+    }
+}
+
+/// Lab speakers get quieter with distance and behind walls.
+/// They are not muffled: `bevy_kira_audio` does not expose kira's filter effects
+fn adjust_volume_on_distance(
+    time: Res<Time>,
+    rapier: Res<RapierContext>,
+    head: Query<&GlobalTransform, With<PlayerHead>>,
+    speakers: Query<(&GlobalTransform, &LabSpeaker)>,
+    voices: VoiceChannels,
+    mut occlusion: Local<HashMap<VoiceChannel, f32>>,
+) {
+    let head = head.single();
+    for channel in [VoiceChannel::SpawnRoom, VoiceChannel::FirstRoom] {
+        let nearest = speakers
+            .iter()
+            .filter(|(_, speaker)| speaker.0 == channel)
+            .map(|(t, _)| t.translation() - head.translation())
+            .min_by(|a, b| a.length().total_cmp(&b.length()));
+        let Some(sound_path) = nearest else {
+            // no speaker in this level, the mixer levels still apply
+            voices.set_spatial(channel, 1.0, 0.5);
+            continue;
+        };
+
+        let walls = rapier.cast_ray(
+            head.translation(),
+            sound_path,
+            1.0 - SPEAKER_CLEARANCE / sound_path.length().max(SPEAKER_CLEARANCE),
+            true,
+            QueryFilter::only_fixed().exclude_sensors(),
+        );
+        let occluded = occlusion.entry(channel).or_default();
+        let step = time.delta_seconds() / OCCLUSION_TIME;
+        *occluded = if walls.is_some() {
+            (*occluded + step).min(1.0)
+        } else {
+            (*occluded - step).max(0.0)
+        };
+
+        let volume = distance_volume(sound_path, MAX_SPEAKER_DISTANCE).max(MIN_SPEAKER_VOLUME)
+            * (1.0 - (1.0 - OCCLUDED_VOLUME) * *occluded);
+        // sound coming around a wall is heard from all around
+        let direct = panning(head, sound_path);
+        let panning = direct + (0.5 - direct) * *occluded;
+        voices.set_spatial(channel, volume as f64, panning as f64);
+    }
+    // BVJ-3 is heard inside the head
+    voices.set_spatial(VoiceChannel::Protagonist, 1.0, 0.5);
 }
 
-fn stop_all_dialogue(audio: Res<AudioChannel<SpawnRoomSpeaker>>) {
-    audio.stop();
+fn stop_all_dialogue(voices: VoiceChannels, mut queue: ResMut<DialogueQueue>) {
+    queue.stop();
+    voices.stop();
 }
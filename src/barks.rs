@@ -0,0 +1,106 @@
+use bevy::prelude::*;
+
+use crate::{
+    dialogue::{DialoguePriority, DialogueQueue, DialogueScript, DialogueScriptHandle},
+    zones::{InsideZones, ZoneTracking, ZoneTrackingPlugin},
+    AppState,
+};
+
+pub(crate) struct BarksPlugin;
+
+impl Plugin for BarksPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<Route>()
+            .add_plugin(ZoneTrackingPlugin::<RouteZone>::default())
+            .add_plugin(ZoneTrackingPlugin::<OffRouteZone>::default())
+            .add_system(bark.after(ZoneTracking).in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Where BVJ-3 is supposed to walk, `Route` in the level
+#[derive(Component)]
+pub struct RouteZone;
+
+/// Where BVJ-3 should not be, `OffRoute` in the level
+#[derive(Component)]
+pub struct OffRouteZone;
+
+/// Dialogue phase the doctors bark from
+static BARK_PHASE: &str = "off_route";
+/// Seconds between two barks
+static BARK_COOLDOWN: f32 = 8.0;
+/// Seconds off the route before the doctors complain again
+static LINGER_TIME: f32 = 12.0;
+
+#[derive(Resource, Default)]
+struct Route {
+    was_off_route: bool,
+    /// Seconds since the last bark or since leaving the route
+    off_route_for: f32,
+    cooldown: f32,
+    /// The doctors have something to say
+    pending: bool,
+    /// Barks of the pool said since it was last exhausted
+    said: Vec<String>,
+}
+
+/// The doctors tell BVJ-3 off for leaving the route or staying off it
+fn bark(
+    time: Res<Time>,
+    mut route: ResMut<Route>,
+    mut queue: ResMut<DialogueQueue>,
+    route_zones: Query<(), With<RouteZone>>,
+    on_route: Res<InsideZones<RouteZone>>,
+    off_route: Res<InsideZones<OffRouteZone>>,
+    script: Res<DialogueScriptHandle>,
+    scripts: Res<Assets<DialogueScript>>,
+) {
+    let Some(script) = scripts.get(&script.0) else {
+        return;
+    };
+    route.cooldown -= time.delta_seconds();
+
+    let is_off_route = !off_route.is_empty() || (!route_zones.is_empty() && on_route.is_empty());
+    if !is_off_route {
+        route.was_off_route = false;
+        route.pending = false;
+        return;
+    }
+    route.off_route_for += time.delta_seconds();
+    if !route.was_off_route || route.off_route_for >= LINGER_TIME {
+        route.pending = true;
+        route.off_route_for = 0.0;
+    }
+    route.was_off_route = true;
+    if !route.pending || route.cooldown > 0.0 || queue.is_busy() {
+        return;
+    }
+
+    let pool: Vec<&str> = script
+        .phase_lines(BARK_PHASE)
+        .map(|l| l.id.as_str())
+        .collect();
+    let mut fresh: Vec<&str> = pool
+        .iter()
+        .copied()
+        .filter(|id| !route.said.iter().any(|s| s == id))
+        .collect();
+    if fresh.is_empty() {
+        // start over, but not with the bark just said
+        let last = route.said.pop();
+        route.said.clear();
+        fresh = pool
+            .into_iter()
+            .filter(|id| Some(*id) != last.as_deref())
+            .collect();
+    }
+    if fresh.is_empty() {
+        return;
+    }
+    let id = fresh[fastrand::usize(..fresh.len())].to_string();
+    info!("Bark {id}");
+    queue.say(id.clone(), DialoguePriority::Bark);
+    route.said.push(id);
+    route.pending = false;
+    route.cooldown = BARK_COOLDOWN;
+}
@@ -0,0 +1,231 @@
+//! Checks the roles of the level nodes without starting the game:
+//! `cargo run --bin validate_level -- assets/bvj-3-level-7.glb`
+//!
+//! Prints the report as JSON, exits with 1 if the level has errors.
+//! Sensors are checked against the story triggers of `assets/story/main.story.json`
+
+use std::process::ExitCode;
+
+// the same parsing the game uses
+use bevy_jam_3::extras::{collider_shape, parse_extras, NodeRole, Problem};
+use serde::{de::IgnoredAny, Deserialize, Serialize};
+
+#[derive(Deserialize)]
+struct Gltf {
+    #[serde(default)]
+    nodes: Vec<Node>,
+}
+
+#[derive(Deserialize)]
+struct Node {
+    name: Option<String>,
+    mesh: Option<usize>,
+    extras: Option<serde_json::Value>,
+}
+
+/// Story graph as far as the level is concerned, the game reads all of it
+#[derive(Deserialize)]
+struct Story {
+    states: Vec<StoryState>,
+}
+
+#[derive(Deserialize)]
+struct StoryState {
+    name: String,
+    #[serde(default)]
+    transitions: Vec<StoryTransition>,
+}
+
+#[derive(Deserialize)]
+struct StoryTransition {
+    on: StoryEvent,
+}
+
+#[derive(Deserialize)]
+enum StoryEvent {
+    Trigger(String),
+    DialogueFinished(IgnoredAny),
+}
+
+static STORY: &str = "assets/story/main.story.json";
+
+#[derive(Serialize)]
+struct Report {
+    level: String,
+    roles: usize,
+    errors: Vec<Problem>,
+    warnings: Vec<Problem>,
+}
+
+impl Report {
+    fn new(level: &str) -> Self {
+        Report {
+            level: level.to_string(),
+            roles: 0,
+            errors: vec![],
+            warnings: vec![],
+        }
+    }
+
+    fn error(&mut self, node: &str, message: String) {
+        self.errors.push(Problem {
+            node: node.to_string(),
+            message,
+        });
+    }
+
+    fn warning(&mut self, node: &str, message: String) {
+        self.warnings.push(Problem {
+            node: node.to_string(),
+            message,
+        });
+    }
+}
+
+/// JSON part of a `.glb`, or the whole `.gltf`
+fn gltf_json(bytes: &[u8]) -> Result<&[u8], String> {
+    if !bytes.starts_with(b"glTF") {
+        return Ok(bytes);
+    }
+    let u32_at = |at: usize| {
+        bytes
+            .get(at..at + 4)
+            .map(|b| u32::from_le_bytes(b.try_into().unwrap()) as usize)
+    };
+    // 12 bytes of header, then the first chunk is the JSON one
+    let length = u32_at(12).ok_or("Truncated glb")?;
+    if u32_at(16) != Some(0x4E4F534A) {
+        return Err("No JSON chunk in glb".to_string());
+    }
+    bytes
+        .get(20..20 + length)
+        .ok_or_else(|| "Truncated glb".to_string())
+}
+
+fn validate(level: &str, gltf: &Gltf, story: &Story) -> Report {
+    let mut report = Report::new(level);
+    let mut found = vec![];
+    let story_triggers: Vec<(&str, &str)> = story
+        .states
+        .iter()
+        .flat_map(|state| {
+            state.transitions.iter().filter_map(|t| match &t.on {
+                StoryEvent::Trigger(trigger) => Some((state.name.as_str(), trigger.as_str())),
+                StoryEvent::DialogueFinished(_) => None,
+            })
+        })
+        .collect();
+    for (i, node) in gltf.nodes.iter().enumerate() {
+        let Some(extras) = &node.extras else { continue };
+        let name = node.name.clone().unwrap_or_else(|| format!("#{i}"));
+        let extras = extras.to_string();
+        if let Err(e) = collider_shape(&extras) {
+            report.error(&name, e);
+        }
+        let role = match parse_extras(&extras) {
+            Ok(role) => role,
+            Err(e) => {
+                report.error(&name, e);
+                continue;
+            }
+        };
+        report.roles += 1;
+        // the game only picks up nodes that have a mesh
+        if node.mesh.is_none() {
+            report.error(&name, format!("{role:?} without a mesh"));
+        }
+        match &role {
+            NodeRole::LaserHeight { show_on: None } | NodeRole::LaserWidth { show_on: None } => {
+                report.warning(&name, "Laser without show_on is always visible".to_string())
+            }
+            NodeRole::Speaker { lines, .. } if lines.is_empty() => {
+                report.error(&name, "Speaker without lines".to_string())
+            }
+            NodeRole::Sensor { .. } => {
+                let trigger = role.trigger().unwrap();
+                if !story_triggers.iter().any(|(_, t)| *t == trigger) {
+                    report.error(&name, format!("No story transition on {trigger}"))
+                }
+            }
+            _ => {}
+        }
+        found.push(role);
+    }
+    for (state, trigger) in story_triggers {
+        if !found
+            .iter()
+            .any(|role| role.trigger().as_deref() == Some(trigger))
+        {
+            report.error(
+                trigger,
+                format!("Story state {state} waits for a node not in the level"),
+            )
+        }
+    }
+    let count = |is: fn(&NodeRole) -> bool| found.iter().filter(|role| is(role)).count();
+    let required = [
+        ("PlayerSpawn", count(|r| matches!(r, NodeRole::PlayerSpawn))),
+        (
+            "PlayerSpawnLookAt",
+            count(|r| matches!(r, NodeRole::PlayerSpawnLookAt)),
+        ),
+        ("ExitLevel", count(|r| matches!(r, NodeRole::ExitLevel))),
+    ];
+    for (role, count) in required {
+        match count {
+            0 => report.error(role, "Missing".to_string()),
+            // a level can have several exits
+            n if n > 1 && role != "ExitLevel" => {
+                report.error(role, format!("{n} nodes, only one is used"))
+            }
+            _ => {}
+        }
+    }
+    report
+}
+
+fn main() -> ExitCode {
+    let levels: Vec<String> = std::env::args().skip(1).collect();
+    if levels.is_empty() {
+        eprintln!("Usage: validate_level <level.glb>...");
+        return ExitCode::from(2);
+    }
+    let story = match std::fs::read(STORY)
+        .map_err(|e| e.to_string())
+        .and_then(|bytes| serde_json::from_slice::<Story>(&bytes).map_err(|e| e.to_string()))
+    {
+        Ok(story) => story,
+        Err(e) => {
+            eprintln!("{STORY}: {e}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut failed = false;
+    let reports: Vec<Report> = levels
+        .iter()
+        .map(|level| {
+            let gltf = std::fs::read(level)
+                .map_err(|e| e.to_string())
+                .and_then(|bytes| {
+                    let json = gltf_json(&bytes)?;
+                    serde_json::from_slice::<Gltf>(json).map_err(|e| e.to_string())
+                });
+            let report = match gltf {
+                Ok(gltf) => validate(level, &gltf, &story),
+                Err(e) => {
+                    let mut report = Report::new(level);
+                    report.error(level, e);
+                    report
+                }
+            };
+            failed |= !report.errors.is_empty();
+            report
+        })
+        .collect();
+    println!("{}", serde_json::to_string_pretty(&reports).unwrap());
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
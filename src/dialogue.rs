@@ -0,0 +1,619 @@
+use std::collections::VecDeque;
+
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashMap};
+use bevy_kira_audio::{AudioInstance, AudioSource, AudioTween, PlaybackState};
+use serde::Deserialize;
+
+use crate::{
+    audio::{VoiceChannel, VoiceChannels},
+    hud::{SubtitleLine, SubtitleTrigger},
+    json_asset::{JsonAsset, JsonLoader},
+    locale::Localizer,
+    settings::Settings,
+    story::{GameState, StoryEvent, StoryFlags, StoryGraph, StoryGraphHandle},
+    AppState,
+};
+
+pub(crate) struct DialoguePlugin;
+
+impl Plugin for DialoguePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<DialogueScript>()
+            .init_asset_loader::<JsonLoader<DialogueScript>>()
+            .init_resource::<DialogueScriptHandle>()
+            .init_resource::<DialogueAudio>()
+            .init_resource::<DialogueQueue>()
+            .init_resource::<DialoguePlaying>()
+            .init_resource::<ReplyChoices>()
+            .add_event::<LineFinished>()
+            .add_system(preload_dialogue_audio)
+            .add_systems((dialogue, run_dialogue_queue).in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Every voice line of the game, in the order it is played
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "6c3f0d0e-5b8a-4f3b-9a53-2f0f3f7c1e21"]
+pub struct DialogueScript {
+    #[serde(default)]
+    pub speakers: HashMap<String, SpeakerStyle>,
+    pub lines: Vec<DialogueLine>,
+}
+
+#[derive(Deserialize)]
+pub struct SpeakerStyle {
+    /// Subtitle colour of the speaker name, e.g. `"#7fd4ff"`
+    pub color: String,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct DialogueLine {
+    pub id: String,
+    pub speaker: String,
+    /// File name inside `sounds/dialogues/`, the line is only shown as a subtitle without it
+    pub audio: Option<String>,
+    pub text: String,
+    #[serde(default)]
+    pub channel: VoiceChannel,
+    /// Lines of a phase are played one after another once the story reaches it
+    pub phase: String,
+    /// The player picks one of these replies instead of saying the line right away
+    #[serde(default)]
+    pub choices: Vec<ReplyChoice>,
+    /// Only said if this story flag is set
+    pub if_flag: Option<String>,
+    /// Only said if this story flag is not set
+    pub unless_flag: Option<String>,
+}
+
+#[derive(Deserialize, Clone)]
+pub struct ReplyChoice {
+    pub text: String,
+    /// Line to say instead of the one with the choices
+    pub line: Option<String>,
+    /// Story flag the reply sets
+    pub flag: Option<String>,
+}
+
+impl DialogueLine {
+    pub fn audio_path(&self) -> Option<String> {
+        self.audio
+            .as_ref()
+            .map(|audio| String::from("sounds/dialogues/") + audio)
+    }
+
+    fn is_said(&self, flags: &StoryFlags) -> bool {
+        self.if_flag.as_ref().is_none_or(|f| flags.0.contains(f))
+            && !self
+                .unless_flag
+                .as_ref()
+                .is_some_and(|f| flags.0.contains(f))
+    }
+}
+
+impl DialogueScript {
+    pub fn line(&self, id: &str) -> Option<&DialogueLine> {
+        self.lines.iter().find(|l| l.id == id)
+    }
+
+    pub fn speaker_color(&self, speaker: &str) -> Color {
+        self.speakers
+            .get(speaker)
+            .and_then(|s| Color::hex(s.color.trim_start_matches('#')).ok())
+            .unwrap_or(Color::WHITE)
+    }
+
+    pub fn phase_lines<'a>(&'a self, phase: &'a str) -> impl Iterator<Item = &'a DialogueLine> {
+        self.lines.iter().filter(move |l| l.phase == phase)
+    }
+
+    /// Index of the line that finishes the phase
+    pub fn phase_end(&self, phase: &str) -> Option<usize> {
+        self.lines.iter().rposition(|l| l.phase == phase)
+    }
+}
+
+#[derive(Resource)]
+pub struct DialogueScriptHandle(pub Handle<DialogueScript>);
+
+impl FromWorld for DialogueScriptHandle {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        DialogueScriptHandle(asset_server.load("text/main.dialogue.json"))
+    }
+}
+
+impl JsonAsset for DialogueScript {
+    const EXTENSIONS: &'static [&'static str] = &["dialogue.json"];
+}
+
+/// Keeps the voice lines loaded so they start without a delay
+#[derive(Resource, Default)]
+pub struct DialogueAudio(Vec<Handle<AudioSource>>);
+
+fn preload_dialogue_audio(
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<AssetEvent<DialogueScript>>,
+    scripts: Res<Assets<DialogueScript>>,
+    mut preloaded: ResMut<DialogueAudio>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        let Some(script) = scripts.get(handle) else {
+            continue;
+        };
+        preloaded.0 = script
+            .lines
+            .iter()
+            .filter_map(|l| l.audio_path())
+            .map(|path| asset_server.load(path))
+            .collect();
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug)]
+pub enum DialoguePriority {
+    /// Dropped if anything else is being said
+    Bark,
+    /// Waits for its turn, starts over if interrupted
+    Story,
+    /// Interrupts anything less urgent
+    Urgent,
+}
+
+struct QueuedLine {
+    id: String,
+    priority: DialoguePriority,
+    /// Lab speaker the doctors talk through instead of the line's own channel
+    speaker: Option<VoiceChannel>,
+}
+
+struct PlayingLine {
+    line: QueuedLine,
+    channel: VoiceChannel,
+    /// `None` when voices are muted
+    voice: Option<(Handle<AudioInstance>, Handle<AudioSource>)>,
+    started: f64,
+    reading_time: f64,
+}
+
+impl PlayingLine {
+    fn is_finished(&self, voices: &VoiceChannels, now: f64) -> bool {
+        let read = now - self.started >= self.reading_time;
+        let Some((instance, _)) = &self.voice else {
+            return read;
+        };
+        match voices.state(self.channel, instance) {
+            PlaybackState::Stopped => true,
+            // the voice failed to load or there is no audio device, go on with subtitles only
+            PlaybackState::Queued if read => {
+                warn!("Voice of line {} did not start, skipping it", self.line.id);
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Reading speed for lines shown without a voice
+static CHARS_PER_SECOND: f64 = 15.0;
+static MIN_READING_TIME: f64 = 2.0;
+
+/// How long a line stays on screen when there is no voice to wait for
+pub fn reading_time(text: &str) -> f64 {
+    (text.chars().count() as f64 / CHARS_PER_SECOND).max(MIN_READING_TIME)
+}
+
+/// Every voice line goes through here so only one is heard at a time
+#[derive(Resource, Default)]
+pub struct DialogueQueue {
+    queued: VecDeque<QueuedLine>,
+    playing: Option<PlayingLine>,
+    /// Voices given up on before they started, stopped if they ever do
+    skipped: Vec<Handle<AudioInstance>>,
+}
+
+/// The line with this id was said till the end
+pub struct LineFinished(pub String);
+
+impl DialogueQueue {
+    pub fn say(&mut self, id: impl Into<String>, priority: DialoguePriority) {
+        self.push(QueuedLine {
+            id: id.into(),
+            priority,
+            speaker: None,
+        });
+    }
+
+    pub fn say_through(
+        &mut self,
+        id: impl Into<String>,
+        priority: DialoguePriority,
+        speaker: VoiceChannel,
+    ) {
+        self.push(QueuedLine {
+            id: id.into(),
+            priority,
+            speaker: Some(speaker),
+        });
+    }
+
+    /// Someone is talking or about to
+    pub fn is_busy(&self) -> bool {
+        self.playing.is_some() || !self.queued.is_empty()
+    }
+
+    fn push(&mut self, line: QueuedLine) {
+        if line.priority == DialoguePriority::Bark && self.is_busy() {
+            info!("Dropping bark {}, someone is talking", line.id);
+            return;
+        }
+        let n = self
+            .queued
+            .iter()
+            .position(|q| q.priority < line.priority)
+            .unwrap_or(self.queued.len());
+        self.queued.insert(n, line);
+    }
+
+    /// Put an interrupted line in front of everything it did not yield to
+    fn requeue(&mut self, line: QueuedLine) {
+        let n = self
+            .queued
+            .iter()
+            .position(|q| q.priority <= line.priority)
+            .unwrap_or(self.queued.len());
+        self.queued.insert(n, line);
+    }
+
+    fn should_interrupt(&self) -> bool {
+        let (Some(playing), Some(next)) = (&self.playing, self.queued.front()) else {
+            return false;
+        };
+        next.priority == DialoguePriority::Urgent && next.priority > playing.line.priority
+    }
+
+    /// Takes off the playing line when an urgent one waits, a story line is said again later.
+    /// `stop` stops a voice and tells if it had started, one that had not is stopped once it does
+    fn interrupt(&mut self, stop: impl FnMut(&Handle<AudioInstance>) -> bool) -> Option<String> {
+        if !self.should_interrupt() {
+            return None;
+        }
+        let playing = self.playing.take()?;
+        info!("Interrupting {}", playing.line.id);
+        let id = playing.line.id.clone();
+        self.skip_voice(playing.voice.map(|(instance, _)| instance), stop);
+        if playing.line.priority == DialoguePriority::Story {
+            self.requeue(playing.line);
+        }
+        Some(id)
+    }
+
+    fn skip_voice(
+        &mut self,
+        voice: Option<Handle<AudioInstance>>,
+        mut stop: impl FnMut(&Handle<AudioInstance>) -> bool,
+    ) {
+        if let Some(instance) = voice.filter(|instance| !stop(instance)) {
+            self.skipped.push(instance);
+        }
+    }
+
+    /// A voice that was only slow to load would start over the next line
+    fn stop_skipped(&mut self, mut stop: impl FnMut(&Handle<AudioInstance>) -> bool) {
+        self.skipped.retain(|instance| !stop(instance));
+    }
+
+    /// Leaving the game, story lines are said again on return
+    pub fn stop(&mut self) {
+        if let Some(playing) = self.playing.take() {
+            self.requeue(playing.line);
+        }
+        self.queued
+            .retain(|q| q.priority == DialoguePriority::Story);
+    }
+}
+
+fn run_dialogue_queue(
+    asset_server: Res<AssetServer>,
+    mut queue: ResMut<DialogueQueue>,
+    voices: VoiceChannels,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    mut subtitles: EventWriter<SubtitleTrigger>,
+    mut finished: EventWriter<LineFinished>,
+    script: Res<DialogueScriptHandle>,
+    scripts: Res<Assets<DialogueScript>>,
+    localizer: Localizer,
+    settings: Res<Settings>,
+    time: Res<Time>,
+) {
+    let Some(script) = scripts.get(&script.0) else {
+        return;
+    };
+    let now = time.elapsed_seconds_f64();
+
+    let mut stop = |instance: &Handle<AudioInstance>| match audio_instances.get_mut(instance) {
+        Some(instance) => {
+            instance.stop(AudioTween::default());
+            true
+        }
+        None => false,
+    };
+    queue.stop_skipped(&mut stop);
+    queue.interrupt(&mut stop);
+
+    if let Some(playing) = &queue.playing {
+        if !playing.is_finished(&voices, now) {
+            return;
+        }
+        finished.send(LineFinished(playing.line.id.clone()));
+        let voice = playing.voice.as_ref().map(|(instance, _)| instance.clone());
+        let channel = playing.channel;
+        queue.playing = None;
+        queue.skip_voice(voice, |instance| {
+            !matches!(voices.state(channel, instance), PlaybackState::Queued)
+        });
+        if queue.queued.is_empty() {
+            subtitles.send(SubtitleTrigger(None));
+        }
+    }
+
+    while let Some(next) = queue.queued.pop_front() {
+        let Some(line) = script.line(&next.id) else {
+            warn!("Unknown dialogue line {}", next.id);
+            finished.send(LineFinished(next.id));
+            continue;
+        };
+        let channel = match (line.channel, next.speaker) {
+            (VoiceChannel::Protagonist, _) | (_, None) => line.channel,
+            (_, Some(speaker)) => speaker,
+        };
+        let text = localizer.text(&format!("line.{}", line.id), &line.text);
+        let reading_time = reading_time(text);
+        let voice = line
+            .audio_path()
+            .filter(|_| !settings.muted_voices)
+            .map(|path| {
+                let source = asset_server.load(path);
+                (voices.play(channel, source.clone()), source)
+            });
+        subtitles.send(SubtitleTrigger(Some(SubtitleLine {
+            speaker: localizer
+                .text(&format!("speaker.{}", line.speaker), &line.speaker)
+                .to_string(),
+            color: script.speaker_color(&line.speaker),
+            text: text.to_string(),
+            voice: voice.clone(),
+            started: now,
+            reading_time,
+        })));
+        queue.playing = Some(PlayingLine {
+            line: next,
+            channel,
+            voice,
+            started: now,
+            reading_time,
+        });
+        break;
+    }
+}
+
+#[derive(Resource, Default)]
+enum DialoguePlaying {
+    #[default]
+    None,
+    /// Waiting for line `n`, or the reply chosen for it, to be said
+    Queued(usize, String),
+    /// Waiting for the player to pick a reply to line `n`
+    Choosing(usize),
+    /// Nothing left to say until the story moves to the next phase
+    PhaseFinished(usize),
+}
+
+/// Replies the player picks from, empty when there is nothing to choose
+#[derive(Resource, Default)]
+pub struct ReplyChoices {
+    pub options: Vec<String>,
+    pub chosen: Option<usize>,
+}
+
+/// Says the lines of the current story phase one after another
+fn dialogue(
+    mut playing: ResMut<DialoguePlaying>,
+    mut queue: ResMut<DialogueQueue>,
+    mut choices: ResMut<ReplyChoices>,
+    mut flags: ResMut<StoryFlags>,
+    mut finished: EventReader<LineFinished>,
+    mut story_events: EventWriter<StoryEvent>,
+    localizer: Localizer,
+    game_state: Res<GameState>,
+    script: Res<DialogueScriptHandle>,
+    scripts: Res<Assets<DialogueScript>>,
+    story: Res<StoryGraphHandle>,
+    stories: Res<Assets<StoryGraph>>,
+) {
+    let (Some(script), Some(story)) = (scripts.get(&script.0), stories.get(&story.0)) else {
+        return;
+    };
+    let phase_end = story
+        .dialogue_phase(&game_state)
+        .and_then(|p| script.phase_end(p));
+    let finished: Vec<_> = finished.iter().map(|f| f.0.clone()).collect();
+
+    // line `n` is over, go on with the phase or tell the story it is finished
+    let mut after = |n: usize| {
+        if phase_end.is_some_and(|end| n < end) {
+            Ok(n + 1)
+        } else {
+            story_events.send(StoryEvent::DialogueFinished(script.lines[n].phase.clone()));
+            Err(DialoguePlaying::PhaseFinished(n))
+        }
+    };
+
+    let mut next = match &*playing {
+        DialoguePlaying::None => Ok(0),
+        DialoguePlaying::Queued(n, id) if finished.contains(id) => after(*n),
+        DialoguePlaying::PhaseFinished(n) if phase_end.is_some_and(|end| *n < end) => Ok(n + 1),
+        DialoguePlaying::Choosing(n) => {
+            let Some(chosen) = choices.chosen.take() else {
+                return;
+            };
+            let line = &script.lines[*n];
+            let choice = &line.choices[chosen];
+            if let Some(flag) = &choice.flag {
+                info!("Story flag {flag}");
+                flags.0.insert(flag.clone());
+            }
+            choices.options.clear();
+            let id = choice.line.clone().unwrap_or_else(|| line.id.clone());
+            queue.say(id.clone(), DialoguePriority::Story);
+            *playing = DialoguePlaying::Queued(*n, id);
+            return;
+        }
+        _ => return,
+    };
+
+    *playing = loop {
+        let n = match next {
+            Ok(n) => n,
+            Err(state) => break state,
+        };
+        let line = &script.lines[n];
+        if !line.is_said(&flags) {
+            next = after(n);
+            continue;
+        }
+        info!("Continuing to {n}");
+        if !line.choices.is_empty() {
+            choices.options = line
+                .choices
+                .iter()
+                .enumerate()
+                .map(|(i, c)| {
+                    localizer
+                        .text(&format!("choice.{}.{}", line.id, i + 1), &c.text)
+                        .to_string()
+                })
+                .collect();
+            choices.chosen = None;
+            break DialoguePlaying::Choosing(n);
+        }
+        queue.say(line.id.clone(), DialoguePriority::Story);
+        break DialoguePlaying::Queued(n, line.id.clone());
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use bevy::asset::HandleId;
+
+    use super::*;
+
+    fn voice() -> Handle<AudioInstance> {
+        Handle::weak(HandleId::random::<AudioInstance>())
+    }
+
+    fn queued(queue: &DialogueQueue) -> Vec<&str> {
+        queue.queued.iter().map(|q| q.id.as_str()).collect()
+    }
+
+    /// What `run_dialogue_queue` does with the next line
+    fn play_next(queue: &mut DialogueQueue, voice: Option<Handle<AudioInstance>>) {
+        let line = queue.queued.pop_front().unwrap();
+        queue.playing = Some(PlayingLine {
+            line,
+            channel: VoiceChannel::SpawnRoom,
+            voice: voice.map(|v| (v, Handle::default())),
+            started: 0.0,
+            reading_time: MIN_READING_TIME,
+        });
+    }
+
+    #[test]
+    fn lines_wait_by_priority() {
+        let mut queue = DialogueQueue::default();
+        queue.say("bark", DialoguePriority::Bark);
+        assert_eq!(queued(&queue), ["bark"]);
+        queue.say("story", DialoguePriority::Story);
+        queue.say("urgent", DialoguePriority::Urgent);
+        queue.say("later", DialoguePriority::Story);
+        queue.say("dropped", DialoguePriority::Bark);
+        assert_eq!(queued(&queue), ["urgent", "story", "later", "bark"]);
+    }
+
+    #[test]
+    fn urgent_line_interrupts_and_story_line_is_said_again() {
+        let mut queue = DialogueQueue::default();
+        queue.say("story", DialoguePriority::Story);
+        queue.say("next", DialoguePriority::Story);
+        play_next(&mut queue, Some(voice()));
+        assert_eq!(queue.interrupt(|_| true), None);
+
+        queue.say("urgent", DialoguePriority::Urgent);
+        assert_eq!(queue.interrupt(|_| true), Some("story".to_string()));
+        assert!(queue.playing.is_none());
+        assert_eq!(queued(&queue), ["urgent", "story", "next"]);
+
+        // urgent lines do not interrupt each other
+        play_next(&mut queue, Some(voice()));
+        queue.say("urgent too", DialoguePriority::Urgent);
+        assert_eq!(queue.interrupt(|_| true), None);
+    }
+
+    #[test]
+    fn interrupted_bark_is_dropped() {
+        let mut queue = DialogueQueue::default();
+        queue.say("bark", DialoguePriority::Bark);
+        play_next(&mut queue, None);
+        queue.say("urgent", DialoguePriority::Urgent);
+        assert_eq!(queue.interrupt(|_| true), Some("bark".to_string()));
+        assert_eq!(queued(&queue), ["urgent"]);
+    }
+
+    #[test]
+    fn voice_interrupted_before_it_started_is_stopped_later() {
+        let mut queue = DialogueQueue::default();
+        let slow = voice();
+        queue.say("story", DialoguePriority::Story);
+        play_next(&mut queue, Some(slow.clone()));
+        queue.say("urgent", DialoguePriority::Urgent);
+        // still loading, nothing to stop yet
+        queue.interrupt(|_| false);
+        assert_eq!(queue.skipped.len(), 1);
+        assert!(queue.skipped.contains(&slow));
+
+        queue.stop_skipped(|_| false);
+        assert_eq!(queue.skipped.len(), 1);
+        let mut stopped = vec![];
+        queue.stop_skipped(|instance| {
+            stopped.push(instance.clone());
+            true
+        });
+        assert_eq!(stopped, [slow]);
+        assert!(queue.skipped.is_empty());
+    }
+
+    #[test]
+    fn started_voice_is_not_kept() {
+        let mut queue = DialogueQueue::default();
+        queue.say("story", DialoguePriority::Story);
+        play_next(&mut queue, Some(voice()));
+        queue.say("urgent", DialoguePriority::Urgent);
+        queue.interrupt(|_| true);
+        assert!(queue.skipped.is_empty());
+    }
+
+    #[test]
+    fn leaving_the_game_keeps_story_lines() {
+        let mut queue = DialogueQueue::default();
+        queue.say("story", DialoguePriority::Story);
+        queue.say("urgent", DialoguePriority::Urgent);
+        play_next(&mut queue, None);
+        queue.say("next", DialoguePriority::Story);
+        queue.stop();
+        assert!(queue.playing.is_none());
+        assert_eq!(queued(&queue), ["story", "next"]);
+    }
+}
@@ -0,0 +1,283 @@
+//! Custom properties of the level nodes, also parsed by the level validator
+
+use bevy::prelude::{warn, Component, Resource};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+/// What a level node is, from its custom properties:
+/// `{"role": "LaserWidth", "show_on": "04"}`.
+/// Older levels put the parameter into the role, as in `LaserWidth_04`
+#[derive(Deserialize, PartialEq, Debug)]
+#[serde(tag = "role")]
+pub enum NodeRole {
+    PlayerSpawn,
+    PlayerSpawnLookAt,
+    /// Static collider made from the node mesh
+    Collider,
+    ExitLevel,
+    /// Story trigger, `Sensor_<name>`
+    Sensor {
+        name: String,
+    },
+    /// Plays the dialogue lines through a lab speaker, `Speaker_<id>_<id>...`
+    Speaker {
+        lines: Vec<String>,
+        /// Interrupts whatever is being said
+        #[serde(default)]
+        urgent: bool,
+    },
+    /// Laser hidden until the story shows its group, `LaserHeight_<group>`
+    LaserHeight {
+        show_on: Option<String>,
+    },
+    /// `LaserWidth_<group>`
+    LaserWidth {
+        show_on: Option<String>,
+    },
+    /// Lab speaker the voices of the channel come from, `AudioSpeaker_<channel>`
+    AudioSpeaker {
+        channel: VoiceChannel,
+    },
+    AudioZone(AudioZone),
+    Route,
+    OffRoute,
+    /// Replaced with the named node or scene of `bvj-3-lib.glb`, `Prefab_<name>`
+    Prefab {
+        name: String,
+    },
+}
+
+impl NodeRole {
+    pub fn is_laser(&self) -> bool {
+        matches!(
+            self,
+            NodeRole::LaserHeight { .. } | NodeRole::LaserWidth { .. }
+        )
+    }
+
+    /// Trigger the story graph gets when the player enters the node, as in `{"Trigger": "Sensor_04"}`
+    pub fn trigger(&self) -> Option<String> {
+        let with_param = |kind: &str, param: &Option<String>| match param {
+            Some(param) => format!("{kind}_{param}"),
+            None => kind.to_string(),
+        };
+        Some(match self {
+            NodeRole::ExitLevel => "ExitLevel".to_string(),
+            NodeRole::Sensor { name } => format!("Sensor_{name}"),
+            NodeRole::Speaker { lines, .. } => format!("Speaker_{}", lines.join("_")),
+            NodeRole::LaserHeight { show_on } => with_param("LaserHeight", show_on),
+            NodeRole::LaserWidth { show_on } => with_param("LaserWidth", show_on),
+            _ => return None,
+        })
+    }
+}
+
+/// Collider made from the node mesh, set by the `shape` custom property
+/// next to the role: `{"role": "Collider", "shape": "box"}`
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Debug, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum ColliderShape {
+    /// Exact geometry, for large static walls
+    #[default]
+    Trimesh,
+    ConvexHull,
+    ConvexDecomposition,
+    /// Axis-aligned bounding box of the mesh
+    Box,
+    /// Upright capsule around the mesh
+    Capsule,
+}
+
+pub fn collider_shape(extras: &str) -> Result<ColliderShape, String> {
+    let mut extras: serde_json::Map<String, Value> =
+        serde_json::from_str(extras).map_err(|e| format!("Broken extras: {e}"))?;
+    match extras.remove("shape") {
+        Some(shape) => serde_json::from_value(shape).map_err(|e| format!("Shape: {e}")),
+        None => Ok(ColliderShape::default()),
+    }
+}
+
+/// Channel a voice line is played through
+#[derive(Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug, Default, strum::EnumString)]
+pub enum VoiceChannel {
+    #[default]
+    SpawnRoom,
+    FirstRoom,
+    /// BVJ-3 talking, heard inside the head
+    Protagonist,
+}
+
+/// Room sound of an `AudioZone` node:
+/// `{"role": "AudioZone", "ambience": "sounds/ambience/corridor.ogg", "volume": 0.5}`
+#[derive(Component, Deserialize, PartialEq, Clone, Debug)]
+pub struct AudioZone {
+    /// Looped track, path inside `assets/`
+    pub ambience: String,
+    #[serde(default = "full_volume")]
+    pub volume: f32,
+}
+
+fn full_volume() -> f32 {
+    1.0
+}
+
+pub fn parse_extras(extras: &str) -> Result<NodeRole, String> {
+    let mut extras: serde_json::Map<String, Value> =
+        serde_json::from_str(extras).map_err(|e| format!("Broken extras: {e}"))?;
+    let Some(Value::String(role)) = extras.get("role").cloned() else {
+        return Err("No role".to_string());
+    };
+    if let Some((kind, param)) = role.split_once('_') {
+        let (key, value) = match kind {
+            "Sensor" | "Prefab" => ("name", Value::from(param)),
+            "Speaker" => ("lines", Value::from(param.split('_').collect::<Vec<_>>())),
+            "LaserHeight" | "LaserWidth" => ("show_on", Value::from(param)),
+            "AudioSpeaker" => ("channel", Value::from(param)),
+            _ => return Err(format!("Unknown role {role}")),
+        };
+        extras.insert("role".to_string(), Value::from(kind));
+        extras.entry(key).or_insert(value);
+    }
+    serde_json::from_value(Value::Object(extras)).map_err(|e| format!("Role {role}: {e}"))
+}
+
+/// Nodes of the level that could not be used
+#[derive(Resource, Serialize, Default)]
+pub struct LoadReport {
+    /// Scene file of the level
+    pub level: String,
+    pub problems: Vec<Problem>,
+}
+
+#[derive(Serialize)]
+pub struct Problem {
+    pub node: String,
+    pub message: String,
+}
+
+static REPORT_FILE: &str = "load-report.json";
+
+impl LoadReport {
+    /// Starts over for the next level
+    pub fn clear(&mut self, level: &str) {
+        self.level = level.to_string();
+        self.problems.clear();
+    }
+
+    pub fn add(&mut self, node: &str, message: String) {
+        warn!("Level node {node}: {message}");
+        self.problems.push(Problem {
+            node: node.to_string(),
+            message,
+        });
+    }
+
+    /// Writes the report next to the game once the level is loaded
+    pub fn save(&self) {
+        let json = serde_json::to_string_pretty(self).expect("report is always serializable");
+        if let Err(e) = std::fs::write(REPORT_FILE, json) {
+            warn!("Could not save {REPORT_FILE}: {e}");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn legacy_sensor() {
+        assert_eq!(
+            parse_extras(r#"{"role": "Sensor_04"}"#),
+            Ok(NodeRole::Sensor {
+                name: "04".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn legacy_speaker_lines() {
+        assert_eq!(
+            parse_extras(r#"{"role": "Speaker_17_18"}"#),
+            Ok(NodeRole::Speaker {
+                lines: vec!["17".to_string(), "18".to_string()],
+                urgent: false,
+            })
+        );
+    }
+
+    #[test]
+    fn laser_without_group() {
+        assert_eq!(
+            parse_extras(r#"{"role": "LaserHeight"}"#),
+            Ok(NodeRole::LaserHeight { show_on: None })
+        );
+        assert_eq!(
+            parse_extras(r#"{"role": "LaserWidth_04"}"#),
+            Ok(NodeRole::LaserWidth {
+                show_on: Some("04".to_string())
+            })
+        );
+    }
+
+    #[test]
+    fn typed_parameters() {
+        assert_eq!(
+            parse_extras(r#"{"role": "LaserWidth", "show_on": "05"}"#),
+            Ok(NodeRole::LaserWidth {
+                show_on: Some("05".to_string())
+            })
+        );
+        assert_eq!(
+            parse_extras(r#"{"role": "AudioSpeaker_FirstRoom"}"#),
+            Ok(NodeRole::AudioSpeaker {
+                channel: VoiceChannel::FirstRoom
+            })
+        );
+        assert_eq!(
+            parse_extras(r#"{"role": "AudioZone", "ambience": "a.ogg"}"#),
+            Ok(NodeRole::AudioZone(AudioZone {
+                ambience: "a.ogg".to_string(),
+                volume: 1.0
+            }))
+        );
+    }
+
+    #[test]
+    fn property_wins_over_role_suffix() {
+        assert_eq!(
+            parse_extras(r#"{"role": "Sensor_04", "name": "05"}"#),
+            Ok(NodeRole::Sensor {
+                name: "05".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn broken_roles() {
+        assert!(parse_extras(r#"{"role": "Door_01"}"#)
+            .unwrap_err()
+            .contains("Unknown role"));
+        assert!(parse_extras(r#"{"role": "Door"}"#).is_err());
+        assert!(parse_extras(r#"{"role": "AudioSpeaker_Hall"}"#).is_err());
+        assert!(parse_extras(r#"{"role": "Speaker"}"#).is_err());
+        assert_eq!(
+            parse_extras(r#"{"shape": "box"}"#),
+            Err("No role".to_string())
+        );
+        assert!(parse_extras("role").is_err());
+    }
+
+    #[test]
+    fn shapes() {
+        assert_eq!(
+            collider_shape(r#"{"role": "Collider"}"#),
+            Ok(ColliderShape::Trimesh)
+        );
+        assert_eq!(
+            collider_shape(r#"{"role": "Collider", "shape": "convex_hull"}"#),
+            Ok(ColliderShape::ConvexHull)
+        );
+        assert!(collider_shape(r#"{"role": "Collider", "shape": "sphere"}"#).is_err());
+    }
+}
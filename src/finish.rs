@@ -5,16 +5,17 @@ use bevy::{
     sprite::SpriteBundle,
 };
 
-use crate::{menu::MenuScreen, AppState};
+use crate::{locale::Localizer, menu::MenuScreen, AppState};
 
 pub fn spawn_finish_screen(
     mut commands: Commands,
     asset_server: Res<AssetServer>,
     menu_screen: Query<Entity, With<MenuScreen>>,
+    localizer: Localizer,
 ) {
     commands.entity(menu_screen.single()).despawn();
     commands.spawn(SpriteBundle {
-        texture: asset_server.load("screens/win_screen.png"),
+        texture: asset_server.load(localizer.text("screen.finish", "screens/win_screen.png")),
         ..default()
     });
 }
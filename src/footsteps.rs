@@ -0,0 +1,118 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioChannel, AudioControl, AudioSource};
+use bevy_rapier3d::prelude::Velocity;
+
+use crate::{audio::Sfx, game::PlayerEffects, settings::Settings, PlayerBody, PlayerLegs};
+
+static FOOTSTEPS: &[&str] = &[
+    "footsteps1.ogg",
+    "footsteps2.ogg",
+    "footsteps3.ogg",
+    "footsteps4.ogg",
+    "footsteps5.ogg",
+    "footsteps6.ogg",
+];
+
+/// Distance walked between two steps at full height
+static STRIDE: f32 = 0.9;
+/// Slower than this counts as standing still
+static MIN_STEP_SPEED: f32 = 0.5;
+/// Shorter flights are just bumps, not landings
+static MIN_AIR_TIME: f32 = 0.25;
+static STEP_VOLUME: f64 = 0.5;
+
+#[derive(Resource)]
+pub struct Footsteps {
+    steps: Vec<Handle<AudioSource>>,
+    jump: Handle<AudioSource>,
+    last_step: Option<usize>,
+    walked: f32,
+    air_time: f32,
+    was_on_ground: bool,
+}
+
+impl FromWorld for Footsteps {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Footsteps {
+            steps: FOOTSTEPS
+                .iter()
+                .map(|f| asset_server.load(String::from("sounds/steps/") + f))
+                .collect(),
+            jump: asset_server.load("sounds/steps/jump.ogg"),
+            last_step: None,
+            walked: 0.0,
+            air_time: 0.0,
+            was_on_ground: true,
+        }
+    }
+}
+
+impl Footsteps {
+    /// Random step that is never the same as the previous one
+    fn next_step(&mut self) -> Handle<AudioSource> {
+        let n = match self.last_step {
+            None => fastrand::usize(..self.steps.len()),
+            Some(last) => {
+                let n = fastrand::usize(..self.steps.len() - 1);
+                if n >= last {
+                    n + 1
+                } else {
+                    n
+                }
+            }
+        };
+        self.last_step = Some(n);
+        self.steps[n].clone()
+    }
+}
+
+pub(crate) fn footsteps(
+    time: Res<Time>,
+    mut footsteps: ResMut<Footsteps>,
+    sfx: Res<AudioChannel<Sfx>>,
+    settings: Res<Settings>,
+    body: Query<(&Velocity, &PlayerEffects), With<PlayerBody>>,
+    legs: Query<&PlayerLegs>,
+) {
+    let (velocity, effects) = body.single();
+    let on_ground = legs.single().touching_objects > 0;
+
+    // a shrunken BVJ-3 takes short, quiet and high pitched steps
+    let pitch = 1.0 + (1.0 - effects.height as f64) * 0.8;
+    let volume =
+        STEP_VOLUME * (effects.height * effects.width).sqrt() as f64 * settings.mixer.sfx();
+    let stride = STRIDE * effects.height;
+
+    if on_ground && !footsteps.was_on_ground && footsteps.air_time > MIN_AIR_TIME {
+        let step = footsteps.next_step();
+        sfx.play(step)
+            .with_volume(volume * 1.5)
+            .with_playback_rate(pitch * 0.9);
+        footsteps.walked = 0.0;
+    }
+    if !on_ground && footsteps.was_on_ground && velocity.linvel.y > 0.0 {
+        sfx.play(footsteps.jump.clone())
+            .with_volume(volume)
+            .with_playback_rate(pitch);
+    }
+
+    if on_ground {
+        footsteps.air_time = 0.0;
+        let speed = Vec2::new(velocity.linvel.x, velocity.linvel.z).length();
+        if speed > MIN_STEP_SPEED {
+            footsteps.walked += speed * time.delta_seconds();
+            if footsteps.walked >= stride {
+                footsteps.walked = 0.0;
+                let step = footsteps.next_step();
+                sfx.play(step).with_volume(volume).with_playback_rate(pitch);
+            }
+        } else {
+            // so the first step sounds right when starting to walk
+            footsteps.walked = stride;
+        }
+    } else {
+        footsteps.air_time += time.delta_seconds();
+    }
+    footsteps.was_on_ground = on_ground;
+}
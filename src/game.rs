@@ -1,12 +1,13 @@
 use bevy::{input::mouse::MouseMotion, prelude::*};
-use bevy_kira_audio::{AudioChannel, AudioControl};
 use bevy_rapier3d::prelude::{CollisionEvent, ExternalImpulse, Velocity};
+use serde::Deserialize;
 
 use crate::{
-    audio::{SpawnRoomSpeaker, AUDIO_FILES, SUBTITLES},
-    hud::SubtitleTrigger,
+    audio::VoiceChannel,
+    dialogue::{DialoguePriority, DialogueQueue},
     menu::{GameTrigger, ShowOn},
     post_processing::GameCamera,
+    story::{GameState, ShownLasers, StoryGraph, StoryGraphHandle},
     AppState, CameraMenu, PlayerBody, PlayerHead, PlayerLegs, PlayerSpawn,
 };
 
@@ -20,21 +21,15 @@ pub(crate) fn activate_game_camera(
         .for_each(|mut c| c.is_active = true);
 }
 
-#[derive(Resource, PartialEq, Eq)]
-pub enum GameState {
-    JustSpawned,
-    InTestingRoom,
-    TurnOnLaser1,
-    Laser1EffectDiscussion,
-    TurnOnLaser2,
-    Escape,
-}
-
 pub fn spawn_player(
     mut player: Query<(&mut Transform, &PlayerSpawn, &mut PlayerEffects), With<PlayerBody>>,
 ) {
     let (mut player, spawn, mut effects) = player.single_mut();
+    reset_player(&mut player, spawn, &mut effects);
+}
 
+/// Back to the spawn point at full size
+pub fn reset_player(player: &mut Transform, spawn: &PlayerSpawn, effects: &mut PlayerEffects) {
     player.translation = spawn.0 .0;
     let mut target = spawn.0 .1;
     // So we dont tilt the body
@@ -118,7 +113,7 @@ pub enum GrowthState {
     Decreasing,
 }
 
-#[derive(Component, PartialEq)]
+#[derive(Component, Deserialize, PartialEq, Eq, Clone, Copy, Debug)]
 pub enum LaserTrigger {
     Height,
     Width,
@@ -261,95 +256,51 @@ pub(crate) fn check_triggers(
 pub(crate) fn process_triggers(
     mut cmd: Commands,
     mut events: EventReader<GameTrigger>,
-    mut next_state: ResMut<NextState<AppState>>,
-    mut game_state: ResMut<GameState>,
+    game_state: Res<GameState>,
     mut laser_event: EventWriter<LaserTrigger>,
-    audio_channel: Res<AudioChannel<SpawnRoomSpeaker>>,
-    asset_server: Res<AssetServer>,
+    mut dialogue: ResMut<DialogueQueue>,
     triggers: Query<(Entity, &GameTrigger)>,
-    mut subtitle_events: EventWriter<SubtitleTrigger>,
+    story: Res<StoryGraphHandle>,
+    stories: Res<Assets<StoryGraph>>,
 ) {
     for event in events.iter() {
         match event {
-            GameTrigger::ExitLevel => next_state.set(AppState::Finish),
-            GameTrigger::Sensor_04 => {
-                if matches!(*game_state, GameState::JustSpawned) {
-                    *game_state = GameState::InTestingRoom;
-                }
-            }
-            GameTrigger::LaserWidth | GameTrigger::LaserWidth_04 => {
-                if matches!(event, GameTrigger::LaserWidth_04)
-                    && matches!(*game_state, GameState::TurnOnLaser2)
-                {
-                    *game_state = GameState::Escape;
-                }
-                match *game_state {
-                    GameState::TurnOnLaser2 | GameState::Escape => {
-                        laser_event.send(LaserTrigger::Width);
-                    }
-                    _ => {}
-                }
-            }
-            GameTrigger::LaserHeight | GameTrigger::LaserHeight_11 => {
-                if matches!(event, GameTrigger::LaserHeight_11)
-                    && matches!(*game_state, GameState::TurnOnLaser1)
-                {
-                    *game_state = GameState::Laser1EffectDiscussion;
-                }
-                match *game_state {
-                    GameState::TurnOnLaser1
-                    | GameState::Laser1EffectDiscussion
-                    | GameState::TurnOnLaser2
-                    | GameState::Escape => {
-                        laser_event.send(LaserTrigger::Height);
-                    }
-                    _ => {}
+            GameTrigger::LaserWidth(_) | GameTrigger::LaserHeight(_) => {
+                let laser = match event {
+                    GameTrigger::LaserWidth(_) => LaserTrigger::Width,
+                    _ => LaserTrigger::Height,
+                };
+                let Some(story) = stories.get(&story.0) else {
+                    continue;
+                };
+                if story.is_armed(&game_state, &laser) {
+                    laser_event.send(laser);
                 }
             }
-            GameTrigger::Sensor_17 => {
-                audio_channel
-                    .play(asset_server.load(String::from("sounds/dialogues/") + AUDIO_FILES[16]));
+            GameTrigger::Speaker { lines, urgent } => {
                 for (ent, trigger) in triggers.iter() {
                     if trigger == event {
                         cmd.entity(ent).despawn_recursive()
                     }
                 }
-                subtitle_events.send(SubtitleTrigger(
-                    SUBTITLES.lines().nth(16).unwrap().to_string(),
-                ));
-            }
-            GameTrigger::Sensor_18 => {
-                audio_channel
-                    .play(asset_server.load(String::from("sounds/dialogues/") + AUDIO_FILES[17]));
-                for (ent, trigger) in triggers.iter() {
-                    if trigger == event {
-                        cmd.entity(ent).despawn_recursive()
-                    }
-                }
-                subtitle_events.send(SubtitleTrigger(
-                    SUBTITLES.lines().nth(17).unwrap().to_string(),
-                ));
-            }
-            GameTrigger::Sensor_19 => {
-                audio_channel
-                    .play(asset_server.load(String::from("sounds/dialogues/") + AUDIO_FILES[18]));
-                for (ent, trigger) in triggers.iter() {
-                    if trigger == event {
-                        cmd.entity(ent).despawn_recursive()
-                    }
+                let priority = if *urgent {
+                    DialoguePriority::Urgent
+                } else {
+                    DialoguePriority::Story
+                };
+                // heard from the `AudioSpeaker_FirstRoom` nodes of the level
+                for id in lines {
+                    dialogue.say_through(id, priority, VoiceChannel::FirstRoom);
                 }
-                subtitle_events.send(SubtitleTrigger(
-                    SUBTITLES.lines().nth(18).unwrap().to_string(),
-                ));
             }
             _ => {}
         }
     }
 }
 
-pub fn show_lasers(mut lasers: Query<(&mut Visibility, &ShowOn)>, game_state: Res<GameState>) {
+pub fn show_lasers(mut lasers: Query<(&mut Visibility, &ShowOn)>, shown: Res<ShownLasers>) {
     for (mut vis, show_on) in lasers.iter_mut() {
-        if *game_state == show_on.0 {
+        if shown.0.contains(&show_on.0) {
             *vis = Visibility::Visible;
         }
     }
@@ -1,13 +1,19 @@
 use bevy::{
+    input::mouse::MouseWheel,
     prelude::{
-        default, AssetServer, BuildChildren, Color, Commands, Component, DespawnRecursiveExt,
-        Entity, ImageBundle, NodeBundle, Query, Res, TextBundle, Transform, With, Without, EventReader, EventWriter, Input, KeyCode,
+        default, Added, AssetServer, Assets, BuildChildren, Color, Commands, Component,
+        DespawnRecursiveExt, DetectChanges, Entity, EventReader, Handle, ImageBundle, Input,
+        KeyCode, NodeBundle, Query, Res, ResMut, Resource, TextBundle, Time, Transform, Visibility,
+        With, Without,
+    },
+    text::{Text, TextAlignment, TextSection, TextStyle},
+    ui::{
+        AlignItems, FlexDirection, JustifyContent, PositionType, Size, Style, UiImage, UiRect, Val,
     },
-    text::{TextAlignment, TextStyle, Text},
-    ui::{AlignItems, JustifyContent, PositionType, Size, Style, UiImage, UiRect, Val},
 };
+use bevy_kira_audio::{AudioInstance, AudioSource};
 
-use crate::PlayerBody;
+use crate::{dialogue::ReplyChoices, locale::Localizer, PlayerBody};
 
 #[derive(Component)]
 pub struct Hud;
@@ -18,7 +24,16 @@ pub struct BodyIcon;
 #[derive(Component)]
 pub struct Subtitle;
 
-pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
+#[derive(Component)]
+pub struct HistoryPanel;
+
+#[derive(Component)]
+pub struct HistoryText;
+
+#[derive(Component)]
+pub struct ReplyText;
+
+pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>, localizer: Localizer) {
     commands
         .spawn((
             NodeBundle {
@@ -32,15 +47,76 @@ pub fn spawn_hud(mut commands: Commands, asset_server: Res<AssetServer>) {
             Hud,
         ))
         .with_children(|parent| {
-            parent.spawn((
-                TextBundle::from_section(
-                    "subtitle",
-                    TextStyle {
-                        font: asset_server.load("PublicPixel-z84yD.ttf"),
-                        font_size: 15.0,
-                        color: Color::WHITE,
+            let style = TextStyle {
+                font: localizer.font(),
+                font_size: 15.0,
+                color: Color::WHITE,
+            };
+            parent
+                .spawn((
+                    NodeBundle {
+                        style: Style {
+                            position_type: PositionType::Absolute,
+                            position: UiRect {
+                                top: Val::Px(20.0),
+                                right: Val::Px(20.0),
+                                ..default()
+                            },
+                            size: Size::width(Val::Px(500.0)),
+                            padding: UiRect::all(Val::Px(10.0)),
+                            flex_direction: FlexDirection::Column,
+                            ..default()
+                        },
+                        background_color: Color::rgba(0.0, 0.0, 0.0, 0.7).into(),
+                        visibility: Visibility::Hidden,
+                        ..default()
                     },
-                )
+                    HistoryPanel,
+                ))
+                .with_children(|parent| {
+                    parent.spawn(
+                        TextBundle::from_section(
+                            localizer.text("history.title", "History"),
+                            style.clone(),
+                        )
+                        .with_style(Style {
+                            margin: UiRect::bottom(Val::Px(8.0)),
+                            ..default()
+                        }),
+                    );
+                    parent.spawn((
+                        TextBundle::from_section(
+                            "",
+                            TextStyle {
+                                font_size: 10.0,
+                                ..style.clone()
+                            },
+                        )
+                        .with_style(Style {
+                            max_size: Size::width(Val::Px(480.0)),
+                            ..default()
+                        }),
+                        HistoryText,
+                    ));
+                });
+            parent.spawn((
+                TextBundle::from_section("", style.clone())
+                    .with_text_alignment(TextAlignment::Left)
+                    .with_style(Style {
+                        position_type: PositionType::Absolute,
+                        position: UiRect {
+                            bottom: Val::Px(60.0),
+                            ..default()
+                        },
+                        ..default()
+                    }),
+                ReplyText,
+            ));
+            parent.spawn((
+                TextBundle::from_sections([
+                    TextSection::new("", style.clone()),
+                    TextSection::new("", style),
+                ])
                 .with_text_alignment(TextAlignment::Center)
                 .with_style(Style {
                     position_type: PositionType::Absolute,
@@ -104,10 +180,231 @@ pub fn update_body_icon(
 }
 
 #[derive(Component)]
-pub struct SubtitleTrigger(pub String);
+pub struct SubtitleTrigger(pub Option<SubtitleLine>);
+
+#[derive(Clone)]
+pub struct SubtitleLine {
+    pub speaker: String,
+    pub color: Color,
+    pub text: String,
+    /// Cues follow the playback position of this voice
+    pub voice: Option<(Handle<AudioInstance>, Handle<AudioSource>)>,
+    /// Cues follow the reading time until the voice starts, or if it never does
+    pub started: f64,
+    pub reading_time: f64,
+}
+
+/// Longer lines are shown in parts
+static MAX_CUE_LEN: usize = 90;
+
+/// Splits a line into cues of whole words, preferring to end them on a sentence
+fn split_cues(text: &str) -> Vec<String> {
+    let mut cues = vec![];
+    let mut cue = String::new();
+    // characters, not bytes, so Cyrillic lines are split at the same length
+    let len = |s: &str| s.chars().count();
+    for word in text.split_whitespace() {
+        if !cue.is_empty() && len(&cue) + 1 + len(word) > MAX_CUE_LEN {
+            cues.push(std::mem::take(&mut cue));
+        }
+        if !cue.is_empty() {
+            cue.push(' ');
+        }
+        cue.push_str(word);
+        if word.ends_with(['.', '?', '!', '…']) && len(&cue) > MAX_CUE_LEN / 2 {
+            cues.push(std::mem::take(&mut cue));
+        }
+    }
+    if !cue.is_empty() {
+        cues.push(cue);
+    }
+    cues
+}
+
+#[derive(Resource, Default)]
+pub struct CurrentSubtitle {
+    line: Option<SubtitleLine>,
+    cues: Vec<String>,
+}
+
+impl CurrentSubtitle {
+    /// Cue to show when `progress` of the line was said,
+    /// each cue takes time proportional to its length
+    fn cue(&self, progress: f64) -> &str {
+        let total: usize = self.cues.iter().map(|c| c.chars().count()).sum();
+        let mut said = 0;
+        for cue in &self.cues {
+            said += cue.chars().count();
+            if said as f64 >= progress * total as f64 {
+                return cue;
+            }
+        }
+        self.cues.last().map_or("", |c| c)
+    }
+}
+
+pub fn update_subtitle(
+    mut events: EventReader<SubtitleTrigger>,
+    mut current: ResMut<CurrentSubtitle>,
+    mut subtitle: Query<&mut Text, With<Subtitle>>,
+    audio_instances: Res<Assets<AudioInstance>>,
+    audio_sources: Res<Assets<AudioSource>>,
+    time: Res<Time>,
+) {
+    for SubtitleTrigger(line) in events.iter() {
+        current.cues = line.as_ref().map_or(vec![], |l| split_cues(&l.text));
+        current.line = line.clone();
+    }
+
+    let mut subtitle = subtitle.single_mut();
+    let Some(line) = &current.line else {
+        subtitle.sections[0].value.clear();
+        subtitle.sections[1].value.clear();
+        return;
+    };
+    let progress = line
+        .voice
+        .as_ref()
+        .and_then(|(instance, source)| {
+            let position = audio_instances.get(instance)?.state().position()?;
+            let duration = audio_sources.get(source)?.sound.duration().as_secs_f64();
+            Some(position / duration)
+        })
+        .unwrap_or_else(|| (time.elapsed_seconds_f64() - line.started) / line.reading_time);
+
+    subtitle.sections[0].value = format!("{}: ", line.speaker);
+    subtitle.sections[0].style.color = line.color;
+    subtitle.sections[1].value = current.cue(progress).to_string();
+}
+
+pub struct HistoryEntry {
+    speaker: String,
+    color: Color,
+    text: String,
+}
+
+/// Every line said so far, so story beats missed while dodging lasers can be re-read
+#[derive(Resource, Default)]
+pub struct DialogueHistory {
+    lines: Vec<HistoryEntry>,
+    open: bool,
+    /// How many lines from the newest one the panel is scrolled back
+    scroll: usize,
+}
+
+/// Lines that fit in the history panel
+static HISTORY_PAGE: usize = 12;
+
+pub fn record_history(
+    mut events: EventReader<SubtitleTrigger>,
+    mut history: ResMut<DialogueHistory>,
+) {
+    for SubtitleTrigger(line) in events.iter() {
+        let Some(line) = line else { continue };
+        history.lines.push(HistoryEntry {
+            speaker: line.speaker.clone(),
+            color: line.color,
+            text: line.text.clone(),
+        });
+        // keep the newest line in view
+        if history.scroll > 0 {
+            history.scroll += 1;
+        }
+    }
+}
+
+static REPLY_KEYS: [KeyCode; 3] = [KeyCode::Key1, KeyCode::Key2, KeyCode::Key3];
 
-pub fn update_subtitle(mut events: EventReader<SubtitleTrigger>, mut subtitle: Query<&mut Text, With<Subtitle>>) {
-	for event in events.iter() {
-        subtitle.single_mut().sections[0].value = event.0.clone();
+pub fn choose_reply(keyboard: Res<Input<KeyCode>>, mut choices: ResMut<ReplyChoices>) {
+    if choices.options.is_empty() || choices.chosen.is_some() {
+        return;
+    }
+    let chosen = REPLY_KEYS
+        .iter()
+        .take(choices.options.len())
+        .position(|k| keyboard.just_pressed(*k));
+    if chosen.is_some() {
+        choices.chosen = chosen;
+    }
+}
+
+pub fn update_reply_text(
+    choices: Res<ReplyChoices>,
+    mut text: Query<&mut Text, With<ReplyText>>,
+    added: Query<(), Added<ReplyText>>,
+) {
+    if !choices.is_changed() && added.is_empty() {
+        return;
+    }
+    let mut text = text.single_mut();
+    text.sections[0].value = choices
+        .options
+        .iter()
+        .enumerate()
+        .map(|(n, option)| format!("{}. {option}\n", n + 1))
+        .collect();
+}
+
+pub fn toggle_history(
+    keyboard: Res<Input<KeyCode>>,
+    mut wheel: EventReader<MouseWheel>,
+    mut history: ResMut<DialogueHistory>,
+) {
+    if keyboard.just_pressed(KeyCode::H) {
+        history.open = !history.open;
+        history.scroll = 0;
+    }
+    let wheel: f32 = wheel.iter().map(|w| w.y).sum();
+    if !history.open {
+        return;
+    }
+    let max_scroll = history.lines.len().saturating_sub(HISTORY_PAGE);
+    if keyboard.just_pressed(KeyCode::Up) || wheel > 0.0 {
+        history.scroll = (history.scroll + 1).min(max_scroll);
+    }
+    if keyboard.just_pressed(KeyCode::Down) || wheel < 0.0 {
+        history.scroll = history.scroll.saturating_sub(1);
+    }
+}
+
+pub fn update_history_panel(
+    history: Res<DialogueHistory>,
+    mut panel: Query<&mut Visibility, With<HistoryPanel>>,
+    mut text: Query<&mut Text, With<HistoryText>>,
+    respawned: Query<(), Added<HistoryText>>,
+) {
+    if !history.is_changed() && respawned.is_empty() {
+        return;
+    }
+    *panel.single_mut() = if history.open {
+        Visibility::Visible
+    } else {
+        Visibility::Hidden
+    };
+
+    let mut text = text.single_mut();
+    let style = text.sections[0].style.clone();
+    let end = history.lines.len() - history.scroll.min(history.lines.len());
+    let start = end.saturating_sub(HISTORY_PAGE);
+    let mut sections = vec![];
+    for entry in &history.lines[start..end] {
+        sections.push(TextSection::new(
+            format!("{}: ", entry.speaker),
+            TextStyle {
+                color: entry.color,
+                ..style.clone()
+            },
+        ));
+        sections.push(TextSection::new(
+            format!("{}\n", entry.text),
+            TextStyle {
+                color: Color::WHITE,
+                ..style.clone()
+            },
+        ));
+    }
+    if sections.is_empty() {
+        sections.push(TextSection::new("", style));
     }
+    text.sections = sections;
 }
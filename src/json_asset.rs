@@ -0,0 +1,39 @@
+use std::marker::PhantomData;
+
+use bevy::{
+    asset::{Asset, AssetLoader, LoadContext, LoadedAsset},
+    utils::BoxedFuture,
+};
+use serde::de::DeserializeOwned;
+
+/// Asset kept in a JSON file of `assets/`, told apart by the extension
+pub trait JsonAsset: Asset + DeserializeOwned {
+    /// As in `main.story.json`
+    const EXTENSIONS: &'static [&'static str];
+}
+
+pub struct JsonLoader<T>(PhantomData<fn() -> T>);
+
+impl<T> Default for JsonLoader<T> {
+    fn default() -> Self {
+        JsonLoader(PhantomData)
+    }
+}
+
+impl<T: JsonAsset> AssetLoader for JsonLoader<T> {
+    fn load<'a>(
+        &'a self,
+        bytes: &'a [u8],
+        load_context: &'a mut LoadContext,
+    ) -> BoxedFuture<'a, Result<(), bevy::asset::Error>> {
+        Box::pin(async move {
+            let asset: T = serde_json::from_slice(bytes)?;
+            load_context.set_default_asset(LoadedAsset::new(asset));
+            Ok(())
+        })
+    }
+
+    fn extensions(&self) -> &[&str] {
+        T::EXTENSIONS
+    }
+}
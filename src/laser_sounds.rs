@@ -0,0 +1,102 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioChannel, AudioControl, AudioInstance, AudioSource, AudioTween};
+
+use crate::{
+    audio::{distance_volume, panning, Sfx},
+    game::LaserTrigger,
+    menu::Laser,
+    settings::Settings,
+    PlayerHead,
+};
+
+/// Hum is heard until this distance from a laser
+static MAX_HUM_DISTANCE: f32 = 8.0;
+static HUM_VOLUME: f64 = 0.4;
+static ZAP_VOLUME: f64 = 0.8;
+
+#[derive(Resource)]
+pub struct LaserSounds {
+    hum: Handle<AudioSource>,
+    zap: Handle<AudioSource>,
+}
+
+impl FromWorld for LaserSounds {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        LaserSounds {
+            hum: asset_server.load("sounds/lasers/hum.ogg"),
+            zap: asset_server.load("sounds/lasers/zap.ogg"),
+        }
+    }
+}
+
+/// Hum of a visible laser
+#[derive(Component)]
+pub struct LaserHum(Handle<AudioInstance>);
+
+impl LaserHum {
+    pub fn stop(&self, audio_instances: &mut Assets<AudioInstance>) {
+        if let Some(instance) = audio_instances.get_mut(&self.0) {
+            instance.stop(AudioTween::default());
+        }
+    }
+}
+
+pub(crate) fn hum_lasers(
+    mut cmd: Commands,
+    sounds: Res<LaserSounds>,
+    settings: Res<Settings>,
+    sfx: Res<AudioChannel<Sfx>>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    head: Query<&GlobalTransform, With<PlayerHead>>,
+    lasers: Query<(Entity, &GlobalTransform, &Visibility, Option<&LaserHum>), With<Laser>>,
+) {
+    let head = head.single();
+    for (ent, transform, visibility, hum) in lasers.iter() {
+        let Some(LaserHum(hum)) = hum else {
+            if visibility != Visibility::Hidden {
+                let hum = sfx
+                    .play(sounds.hum.clone())
+                    .looped()
+                    .with_volume(0.0)
+                    .handle();
+                cmd.entity(ent).insert(LaserHum(hum));
+            }
+            continue;
+        };
+        let Some(instance) = audio_instances.get_mut(hum) else {
+            continue;
+        };
+        let sound_path = transform.translation() - head.translation();
+        let volume = HUM_VOLUME
+            * distance_volume(sound_path, MAX_HUM_DISTANCE) as f64
+            * settings.mixer.sfx();
+        instance.set_volume(volume, AudioTween::default());
+        instance.set_panning(panning(head, sound_path) as f64, AudioTween::default());
+    }
+}
+
+/// Silences the lasers when leaving the game, they start humming again on return
+pub(crate) fn stop_laser_hum(
+    mut cmd: Commands,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    lasers: Query<(Entity, &LaserHum)>,
+) {
+    for (ent, hum) in lasers.iter() {
+        hum.stop(&mut audio_instances);
+        cmd.entity(ent).remove::<LaserHum>();
+    }
+}
+
+pub(crate) fn zap_player(
+    mut events: EventReader<LaserTrigger>,
+    sounds: Res<LaserSounds>,
+    settings: Res<Settings>,
+    sfx: Res<AudioChannel<Sfx>>,
+) {
+    // both lasers at once still make one zap
+    if events.iter().count() > 0 {
+        sfx.play(sounds.zap.clone())
+            .with_volume(ZAP_VOLUME * settings.mixer.sfx());
+    }
+}
@@ -0,0 +1,350 @@
+use bevy::{
+    asset::LoadState,
+    ecs::system::{EntityCommands, SystemParam},
+    gltf::{Gltf, GltfExtras, GltfMesh, GltfNode},
+    prelude::*,
+    reflect::TypeUuid,
+};
+use bevy_kira_audio::AudioInstance;
+use bevy_rapier3d::prelude::Velocity;
+use serde::Deserialize;
+
+use crate::{
+    extras::LoadReport,
+    game::{reset_player, PlayerEffects},
+    json_asset::{JsonAsset, JsonLoader},
+    laser_sounds::LaserHum,
+    menu::{GameTrigger, Processed},
+    AppState, CollidersLoaded, PlayerBody, PlayerSpawn,
+};
+
+pub(crate) struct LevelsPlugin;
+
+impl Plugin for LevelsPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<LevelRegistry>()
+            .init_asset_loader::<JsonLoader<LevelRegistry>>()
+            .init_resource::<Campaign>()
+            .init_resource::<LoadReport>()
+            .init_resource::<PrefabLibrary>()
+            .add_system(load_level)
+            .add_system(reload_level)
+            .add_system(save_load_report)
+            .add_systems((exit_level, respawn_player).in_set(OnUpdate(AppState::InGame)));
+    }
+}
+
+/// Levels of the game in the order they are played
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "4d8e2a7b-1c3f-4b9e-8a6d-7f0e5c2b1a93"]
+pub struct LevelRegistry {
+    /// Scene files inside `assets/`
+    pub levels: Vec<String>,
+}
+
+#[derive(Resource)]
+pub struct Campaign {
+    registry: Handle<LevelRegistry>,
+    /// Index of the level being played
+    pub current: usize,
+    /// The player waits for the level to load before moving there
+    respawn: Option<Respawn>,
+}
+
+#[derive(PartialEq, Debug)]
+enum Respawn {
+    /// Spawn point of the next level
+    Spawn,
+    /// Where the player was before the level was reloaded
+    Stay(Vec3),
+}
+
+impl Campaign {
+    /// Moves on to the next of the `levels`, false if the current one was the last
+    fn advance(&mut self, levels: usize) -> bool {
+        if self.current + 1 >= levels {
+            return false;
+        }
+        self.current += 1;
+        self.respawn = Some(Respawn::Spawn);
+        true
+    }
+}
+
+impl FromWorld for Campaign {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        Campaign {
+            registry: asset_server.load("levels/main.campaign.json"),
+            current: 0,
+            respawn: None,
+        }
+    }
+}
+
+/// Root of the loaded level scene
+#[derive(Component)]
+pub struct LevelScene;
+
+/// Spawned for the current level, removed with it
+#[derive(Component, Default)]
+pub struct LevelEntity;
+
+fn load_level(
+    mut cmd: Commands,
+    asset_server: Res<AssetServer>,
+    campaign: Res<Campaign>,
+    registries: Res<Assets<LevelRegistry>>,
+    scenes: Query<(), With<LevelScene>>,
+    mut report: ResMut<LoadReport>,
+) {
+    if !scenes.is_empty() {
+        return;
+    }
+    let Some(registry) = registries.get(&campaign.registry) else {
+        return;
+    };
+    let Some(level) = registry.levels.get(campaign.current) else {
+        error!("No level {} in the registry", campaign.current);
+        return;
+    };
+    info!("Loading level {level}");
+    report.clear(level);
+    cmd.spawn((
+        SceneBundle {
+            // note that we have to include the `Scene0` label
+            scene: asset_server.load(format!("{level}#Scene0")),
+            ..default()
+        },
+        LevelScene,
+    ));
+}
+
+fn save_load_report(colliders_loaded: Res<CollidersLoaded>, report: Res<LoadReport>) {
+    // colliders are made once every node of the level was processed
+    if colliders_loaded.is_changed() && colliders_loaded.0 {
+        report.save();
+    }
+}
+
+/// Everything spawned for the current level
+#[derive(SystemParam)]
+struct LoadedLevel<'w, 's> {
+    cmd: Commands<'w, 's>,
+    entities: Query<'w, 's, Entity, Or<(With<LevelScene>, With<LevelEntity>)>>,
+    hums: Query<'w, 's, &'static LaserHum>,
+    audio_instances: ResMut<'w, Assets<AudioInstance>>,
+    colliders_loaded: ResMut<'w, CollidersLoaded>,
+}
+
+impl LoadedLevel<'_, '_> {
+    /// `load_level` loads the current level again after this
+    fn unload(&mut self) {
+        for hum in self.hums.iter() {
+            hum.stop(&mut self.audio_instances);
+        }
+        for ent in self.entities.iter() {
+            self.cmd.entity(ent).despawn_recursive();
+        }
+        self.colliders_loaded.0 = false;
+    }
+}
+
+/// Unloads the level at its exit and goes on to the next one,
+/// the game is finished at the exit of the last level
+fn exit_level(
+    mut events: EventReader<GameTrigger>,
+    mut next_state: ResMut<NextState<AppState>>,
+    mut campaign: ResMut<Campaign>,
+    registries: Res<Assets<LevelRegistry>>,
+    mut level: LoadedLevel,
+) {
+    if !events.iter().any(|e| *e == GameTrigger::ExitLevel) {
+        return;
+    }
+    let levels = registries
+        .get(&campaign.registry)
+        .map_or(0, |r| r.levels.len());
+    if campaign.advance(levels) {
+        level.unload();
+    } else {
+        next_state.set(AppState::Finish);
+    }
+}
+
+/// Loads the level again when its file changes on disk,
+/// only with the `hot_reload` feature
+fn reload_level(
+    mut events: EventReader<AssetEvent<Scene>>,
+    scene: Query<&Handle<Scene>, With<LevelScene>>,
+    state: Res<State<AppState>>,
+    mut campaign: ResMut<Campaign>,
+    player: Query<&Transform, With<PlayerBody>>,
+    mut level: LoadedLevel,
+) {
+    let Ok(scene) = scene.get_single() else {
+        return;
+    };
+    let modified = events
+        .iter()
+        .any(|e| matches!(e, AssetEvent::Modified { handle } if handle == scene));
+    if !modified {
+        return;
+    }
+    info!("Level changed, reloading");
+    level.unload();
+    // the player would fall through the floor while the colliders are rebuilt
+    if state.0 == AppState::InGame && campaign.respawn.is_none() {
+        campaign.respawn = Some(Respawn::Stay(player.single().translation));
+    }
+}
+
+/// Moves the player into the new or reloaded level once it is ready to walk on
+fn respawn_player(
+    mut campaign: ResMut<Campaign>,
+    colliders_loaded: Res<CollidersLoaded>,
+    unprocessed: Query<(), (With<GltfExtras>, With<Children>, Without<Processed>)>,
+    mut player: Query<
+        (
+            &mut Transform,
+            &mut Velocity,
+            &PlayerSpawn,
+            &mut PlayerEffects,
+        ),
+        With<PlayerBody>,
+    >,
+) {
+    if campaign.respawn.is_none() || !colliders_loaded.0 || !unprocessed.is_empty() {
+        return;
+    }
+    let (mut transform, mut velocity, spawn, mut effects) = player.single_mut();
+    match campaign.respawn.take() {
+        Some(Respawn::Spawn) => reset_player(&mut transform, spawn, &mut effects),
+        Some(Respawn::Stay(position)) => {
+            transform.translation = position;
+            *velocity = Velocity::zero();
+        }
+        None => {}
+    }
+}
+
+/// Doors, lasers and speakers shared by the levels,
+/// placed with `{"role": "Prefab", "name": "<node or scene>"}`
+#[derive(Resource)]
+pub struct PrefabLibrary(Handle<Gltf>);
+
+impl FromWorld for PrefabLibrary {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        PrefabLibrary(asset_server.load("bvj-3-lib.glb"))
+    }
+}
+
+#[derive(SystemParam)]
+pub struct Prefabs<'w> {
+    library: Res<'w, PrefabLibrary>,
+    asset_server: Res<'w, AssetServer>,
+    gltfs: Res<'w, Assets<Gltf>>,
+    nodes: Res<'w, Assets<GltfNode>>,
+    meshes: Res<'w, Assets<GltfMesh>>,
+}
+
+impl Prefabs<'_> {
+    /// Spawns the named node or scene of the library in place of a level node,
+    /// `Ok(false)` while the library is still loading
+    pub fn spawn(
+        &self,
+        cmd: &mut Commands,
+        name: &str,
+        transform: Transform,
+    ) -> Result<bool, String> {
+        let Some(library) = self.gltfs.get(&self.library.0) else {
+            return match self.asset_server.get_load_state(&self.library.0) {
+                LoadState::Failed => Err("No prefab library".to_string()),
+                _ => Ok(false),
+            };
+        };
+        if let Some(node) = library.named_nodes.get(name) {
+            let node = self.nodes.get(node).ok_or("Prefab node is not loaded")?;
+            let mut prefab = cmd.spawn((SpatialBundle::from_transform(transform), LevelEntity));
+            self.spawn_node(&mut prefab, node);
+        } else {
+            let scene = library
+                .named_scenes
+                .get(name)
+                .ok_or_else(|| format!("No prefab {name}"))?;
+            cmd.spawn((
+                SceneBundle {
+                    scene: scene.clone(),
+                    transform,
+                    ..default()
+                },
+                LevelEntity,
+            ));
+        }
+        Ok(true)
+    }
+
+    /// Same entities the glTF scene would have, so the roles inside work as in the level
+    fn spawn_node(&self, entity: &mut EntityCommands, node: &GltfNode) {
+        if let Some(extras) = &node.extras {
+            entity.insert(extras.clone());
+        }
+        entity.with_children(|parent| {
+            let primitives = node.mesh.as_ref().and_then(|mesh| self.meshes.get(mesh));
+            for primitive in primitives.iter().flat_map(|mesh| &mesh.primitives) {
+                parent.spawn(PbrBundle {
+                    mesh: primitive.mesh.clone(),
+                    material: primitive.material.clone().unwrap_or_default(),
+                    ..default()
+                });
+            }
+            for child in &node.children {
+                let mut child_entity = parent.spawn(SpatialBundle::from_transform(child.transform));
+                self.spawn_node(&mut child_entity, child);
+            }
+        });
+    }
+}
+
+impl JsonAsset for LevelRegistry {
+    const EXTENSIONS: &'static [&'static str] = &["campaign.json"];
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn campaign() -> Campaign {
+        Campaign {
+            registry: Handle::default(),
+            current: 0,
+            respawn: None,
+        }
+    }
+
+    #[test]
+    fn exit_goes_to_the_next_level() {
+        let mut campaign = campaign();
+        assert!(campaign.advance(2));
+        assert_eq!(campaign.current, 1);
+        assert_eq!(campaign.respawn, Some(Respawn::Spawn));
+    }
+
+    #[test]
+    fn exit_of_the_last_level_finishes() {
+        let mut campaign = campaign();
+        assert!(!campaign.advance(1));
+        assert_eq!(campaign.current, 0);
+        assert_eq!(campaign.respawn, None);
+
+        campaign.current = 1;
+        assert!(!campaign.advance(2));
+        assert_eq!(campaign.current, 1);
+    }
+
+    #[test]
+    fn registry_not_loaded_finishes() {
+        assert!(!campaign().advance(0));
+    }
+}
@@ -0,0 +1,3 @@
+//! Parts of the game shared with the `validate_level` tool
+
+pub mod extras;
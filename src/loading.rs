@@ -0,0 +1,177 @@
+use bevy::{
+    asset::LoadState,
+    prelude::*,
+    scene::{SceneInstance, SceneSpawner},
+};
+
+use crate::{
+    dialogue::{DialogueScript, DialogueScriptHandle},
+    levels::LevelScene,
+    locale::Localizer,
+    AppState, CollidersLoaded,
+};
+
+/// Shows a progress bar until the first level and the dialogue are ready,
+/// then opens the menu
+pub(crate) struct LoadingPlugin;
+
+impl Plugin for LoadingPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<LoadingTextures>()
+            .add_system(spawn_loading_screen.in_schedule(OnEnter(AppState::Loading)))
+            .add_systems((check_loading, update_loading_text).in_set(OnUpdate(AppState::Loading)))
+            .add_system(despawn_loading_screen.in_schedule(OnExit(AppState::Loading)));
+    }
+}
+
+static TEXTURES: [&str; 3] = [
+    "screens/start_screen.png",
+    "screens/win_screen.png",
+    "body_icon.png",
+];
+
+/// Keeps the screens and HUD icons loaded
+#[derive(Resource)]
+struct LoadingTextures(Vec<Handle<Image>>);
+
+impl FromWorld for LoadingTextures {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        LoadingTextures(TEXTURES.iter().map(|t| asset_server.load(*t)).collect())
+    }
+}
+
+#[derive(Component)]
+struct LoadingScreen;
+
+#[derive(Component)]
+struct LoadingBar;
+
+#[derive(Component)]
+struct LoadingText;
+
+fn spawn_loading_screen(mut commands: Commands, localizer: Localizer) {
+    commands
+        .spawn((
+            NodeBundle {
+                style: Style {
+                    size: Size::new(Val::Percent(100.0), Val::Percent(100.0)),
+                    flex_direction: FlexDirection::Column,
+                    justify_content: JustifyContent::Center,
+                    align_items: AlignItems::Center,
+                    ..default()
+                },
+                ..default()
+            },
+            LoadingScreen,
+        ))
+        .with_children(|parent| {
+            parent.spawn((
+                TextBundle::from_section(
+                    localizer.text("screen.loading", "Loading"),
+                    TextStyle {
+                        font: localizer.font(),
+                        font_size: 20.0,
+                        color: Color::WHITE,
+                    },
+                ),
+                LoadingText,
+            ));
+            parent
+                .spawn(NodeBundle {
+                    style: Style {
+                        size: Size::new(Val::Px(300.0), Val::Px(12.0)),
+                        margin: UiRect::top(Val::Px(10.0)),
+                        ..default()
+                    },
+                    background_color: Color::DARK_GRAY.into(),
+                    ..default()
+                })
+                .with_children(|parent| {
+                    parent.spawn((
+                        NodeBundle {
+                            style: Style {
+                                size: Size::new(Val::Percent(0.0), Val::Percent(100.0)),
+                                ..default()
+                            },
+                            background_color: Color::WHITE.into(),
+                            ..default()
+                        },
+                        LoadingBar,
+                    ));
+                });
+        });
+}
+
+fn despawn_loading_screen(mut commands: Commands, screen: Query<Entity, With<LoadingScreen>>) {
+    for screen in screen.iter() {
+        commands.entity(screen).despawn_recursive();
+    }
+}
+
+/// The text is spawned before the language table is loaded
+fn update_loading_text(localizer: Localizer, mut text: Query<&mut Text, With<LoadingText>>) {
+    let Ok(mut text) = text.get_single_mut() else {
+        return;
+    };
+    let section = &text.sections[0];
+    let (value, font) = (
+        localizer.text("screen.loading", "Loading"),
+        localizer.font(),
+    );
+    if section.value != value || section.style.font != font {
+        let section = &mut text.sections[0];
+        section.value = value.to_string();
+        section.style.font = font;
+    }
+}
+
+fn check_loading(
+    asset_server: Res<AssetServer>,
+    localizer: Localizer,
+    textures: Res<LoadingTextures>,
+    script: Res<DialogueScriptHandle>,
+    scripts: Res<Assets<DialogueScript>>,
+    scene_spawner: Res<SceneSpawner>,
+    level: Query<&SceneInstance, With<LevelScene>>,
+    colliders: Res<CollidersLoaded>,
+    mut bar: Query<&mut Style, With<LoadingBar>>,
+    mut next_state: ResMut<NextState<AppState>>,
+) {
+    // a missing file will not get any more loaded
+    let is_loaded = |state| matches!(state, LoadState::Loaded | LoadState::Failed);
+    let level_ready = level
+        .get_single()
+        .is_ok_and(|level| scene_spawner.instance_is_ready(**level));
+    let mut steps = vec![
+        is_loaded(asset_server.get_load_state(&script.0)),
+        level_ready,
+        colliders.0,
+        // so the menu does not start in English
+        localizer.is_loaded(&asset_server),
+    ];
+    steps.extend(
+        textures
+            .0
+            .iter()
+            .map(|texture| is_loaded(asset_server.get_load_state(texture))),
+    );
+    // the voice lines are preloaded by the dialogue once the script is there
+    if let Some(script) = scripts.get(&script.0) {
+        steps.extend(
+            script
+                .lines
+                .iter()
+                .filter_map(|line| line.audio_path())
+                .map(|path| is_loaded(asset_server.get_load_state(path.as_str()))),
+        );
+    }
+
+    let done = steps.iter().filter(|done| **done).count();
+    if let Ok(mut bar) = bar.get_single_mut() {
+        bar.size.width = Val::Percent(100.0 * done as f32 / steps.len() as f32);
+    }
+    if done == steps.len() {
+        next_state.set(AppState::Menu);
+    }
+}
@@ -0,0 +1,181 @@
+use std::collections::BTreeSet;
+
+use ab_glyph::Font as _;
+use bevy::{
+    asset::{HandleId, LoadState},
+    ecs::system::SystemParam,
+    prelude::*,
+    reflect::TypeUuid,
+    utils::HashMap,
+};
+use serde::Deserialize;
+
+use crate::{
+    json_asset::{JsonAsset, JsonLoader},
+    settings::Settings,
+};
+
+static FALLBACK_LANGUAGE: &str = "en";
+static DEFAULT_FONT: &str = "PublicPixel-z84yD.ttf";
+
+/// Strings of one language, `assets/locale/<language>.locale.json`
+///
+/// Keys are `line.<id>` and `speaker.<name>` for subtitles
+/// and dotted names like `screen.start` for the UI
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "9b1f4c2e-3d6a-4e8b-a0c7-5f2d1e8b9a34"]
+pub struct LocaleTable {
+    /// Font to use instead of the pixel font, for scripts it does not cover
+    pub font: Option<String>,
+    pub strings: HashMap<String, String>,
+}
+
+#[derive(Resource)]
+pub struct Locale {
+    pub language: String,
+    table: Handle<LocaleTable>,
+    fallback: Handle<LocaleTable>,
+    font: Handle<Font>,
+    glyphs_checked: bool,
+}
+
+impl FromWorld for Locale {
+    fn from_world(world: &mut World) -> Self {
+        let language = language_from_args()
+            .or_else(|| world.resource::<Settings>().language.clone())
+            .unwrap_or_else(|| FALLBACK_LANGUAGE.to_string());
+        info!("Language {language}");
+        let asset_server = world.resource::<AssetServer>();
+        let load_table = |l: &str| asset_server.load(format!("locale/{l}.locale.json"));
+        Locale {
+            table: load_table(&language),
+            fallback: load_table(FALLBACK_LANGUAGE),
+            font: asset_server.load(DEFAULT_FONT),
+            language,
+            glyphs_checked: false,
+        }
+    }
+}
+
+/// `--lang <code>` or `--lang=<code>`
+fn language_from_args() -> Option<String> {
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        if arg == "--lang" {
+            return args.next();
+        }
+        if let Some(language) = arg.strip_prefix("--lang=") {
+            return Some(language.to_string());
+        }
+    }
+    None
+}
+
+#[derive(SystemParam)]
+pub struct Localizer<'w> {
+    locale: Res<'w, Locale>,
+    tables: Res<'w, Assets<LocaleTable>>,
+}
+
+impl Localizer<'_> {
+    /// String in the chosen language, or in English if it is not translated
+    pub fn get(&self, key: &str) -> Option<&str> {
+        [&self.locale.table, &self.locale.fallback]
+            .into_iter()
+            .filter_map(|t| self.tables.get(t))
+            .find_map(|t| t.strings.get(key))
+            .map(String::as_str)
+    }
+
+    pub fn text<'a>(&'a self, key: &str, default: &'a str) -> &'a str {
+        self.get(key).unwrap_or(default)
+    }
+
+    pub fn font(&self) -> Handle<Font> {
+        self.locale.font.clone()
+    }
+
+    /// The string tables and the font of the language are loaded, or failed to load
+    pub fn is_loaded(&self, asset_server: &AssetServer) -> bool {
+        let done = |id: HandleId| {
+            matches!(
+                asset_server.get_load_state(id),
+                LoadState::Loaded | LoadState::Failed
+            )
+        };
+        let font = self
+            .tables
+            .get(&self.locale.table)
+            .and_then(|t| t.font.as_deref())
+            .unwrap_or(DEFAULT_FONT);
+        done(self.locale.table.id())
+            && done(self.locale.fallback.id())
+            // the font is switched once the table arrives
+            && self.locale.font.id() == HandleId::from(font)
+            && done(self.locale.font.id())
+    }
+}
+
+pub(crate) struct LocalePlugin;
+
+impl Plugin for LocalePlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<LocaleTable>()
+            .init_asset_loader::<JsonLoader<LocaleTable>>()
+            .init_resource::<Locale>()
+            .add_systems((apply_locale_font, check_glyph_coverage).chain());
+    }
+}
+
+fn apply_locale_font(
+    asset_server: Res<AssetServer>,
+    mut events: EventReader<AssetEvent<LocaleTable>>,
+    mut locale: ResMut<Locale>,
+    tables: Res<Assets<LocaleTable>>,
+) {
+    for event in events.iter() {
+        let (AssetEvent::Created { handle } | AssetEvent::Modified { handle }) = event else {
+            continue;
+        };
+        if *handle != locale.table {
+            continue;
+        }
+        let Some(table) = tables.get(handle) else {
+            continue;
+        };
+        locale.font = asset_server.load(table.font.as_deref().unwrap_or(DEFAULT_FONT));
+        locale.glyphs_checked = false;
+    }
+}
+
+/// Warns about characters of the chosen language the font would draw as boxes
+fn check_glyph_coverage(
+    mut locale: ResMut<Locale>,
+    tables: Res<Assets<LocaleTable>>,
+    fonts: Res<Assets<Font>>,
+) {
+    if locale.glyphs_checked {
+        return;
+    }
+    let (Some(table), Some(font)) = (tables.get(&locale.table), fonts.get(&locale.font)) else {
+        return;
+    };
+    let missing: BTreeSet<char> = table
+        .strings
+        .values()
+        .flat_map(|s| s.chars())
+        .filter(|c| !c.is_whitespace() && font.font.glyph_id(*c).0 == 0)
+        .collect();
+    if !missing.is_empty() {
+        let missing: String = missing.into_iter().collect();
+        warn!(
+            "Font of locale {} has no glyphs for {missing:?}, set \"font\" in its table",
+            locale.language
+        );
+    }
+    locale.glyphs_checked = true;
+}
+
+impl JsonAsset for LocaleTable {
+    const EXTENSIONS: &'static [&'static str] = &["locale.json"];
+}
@@ -1,15 +1,31 @@
 // Unfortunately component queries are pretty involved
 // make clippy not worry about them
 #![allow(clippy::type_complexity)]
+// systems take their resources as arguments
+#![allow(clippy::too_many_arguments)]
 
 //! A simple 3D scene with light shining over a cube sitting on a plane.
 
+mod ambience;
 mod audio;
+mod barks;
+mod dialogue;
 mod finish;
+mod footsteps;
 mod game;
 mod hud;
+mod json_asset;
+mod laser_sounds;
+mod levels;
+mod loading;
+mod locale;
 mod menu;
+mod mixer;
+mod music;
 mod post_processing;
+mod settings;
+mod story;
+mod zones;
 
 use audio::AudioPlugin;
 use bevy::prelude::*;
@@ -17,23 +33,24 @@ use bevy::render::camera::RenderTarget;
 use bevy::sprite::Material2dPlugin;
 use bevy::window::CursorGrabMode;
 use bevy::window::Window;
+use bevy_jam_3::extras;
 
 use bevy_rapier3d::prelude::ActiveEvents;
 use bevy_rapier3d::prelude::Sensor;
 use bevy_rapier3d::prelude::{
-    Collider, ExternalImpulse, LockedAxes, NoUserData, RapierPhysicsPlugin, RigidBody, Velocity,
+    Collider, CollisionGroups, ExternalImpulse, Group, LockedAxes, NoUserData,
+    RapierPhysicsPlugin, RigidBody, Velocity,
 };
 use game::LaserTrigger;
 use game::check_triggers;
-use game::GameState;
 use game::GrowthState;
 use game::PlayerEffects;
 use game::show_lasers;
 use menu::GameTrigger;
+use menu::SENSOR_VOLUMES;
 use post_processing::setup_postpro;
 use post_processing::BVJPostProcessing;
 use post_processing::GameCamera;
-use serde::Deserialize;
 
 /// Move and yaw
 #[derive(Component)]
@@ -48,7 +65,9 @@ pub struct PlayerSpawn((Vec3, Vec3));
 
 #[derive(Debug, Clone, Copy, Default, Eq, PartialEq, Hash, States)]
 pub enum AppState {
+    /// First level, its colliders and the dialogue
     #[default]
+    Loading,
     Menu,
     InGame,
     Finish,
@@ -65,8 +84,13 @@ pub struct CameraMenu;
 
 fn main() {
     App::new()
-        .add_plugins(DefaultPlugins)
+        .add_plugins(DefaultPlugins.set(AssetPlugin {
+            watch_for_changes: cfg!(feature = "hot_reload"),
+            ..default()
+        }))
         .add_plugin(Material2dPlugin::<BVJPostProcessing>::default())
+        .insert_resource(settings::Settings::load())
+        .add_plugin(locale::LocalePlugin)
         .add_startup_system(setup_postpro.pipe(setup_player))
         .add_state::<AppState>()
         .add_system(grab_mouse)
@@ -75,16 +99,29 @@ fn main() {
         .add_event::<GameTrigger>()
 		.add_event::<LaserTrigger>()
         .add_event::<hud::SubtitleTrigger>()
+        .init_resource::<hud::CurrentSubtitle>()
+        .init_resource::<hud::DialogueHistory>()
         .add_plugin(AudioPlugin)
+        .add_plugin(music::MusicPlugin)
+        .add_plugin(mixer::MixerPlugin)
+        .add_plugin(ambience::AmbiencePlugin)
+        .add_plugin(barks::BarksPlugin)
+        .add_plugin(levels::LevelsPlugin)
+        .add_plugin(loading::LoadingPlugin)
         .add_plugin(RapierPhysicsPlugin::<NoUserData>::default())
-        .insert_resource(GameState::JustSpawned)
+        .add_plugin(story::StoryPlugin)
+        .add_plugin(dialogue::DialoguePlugin)
         .insert_resource(CollidersLoaded(false))
-        .add_startup_system(spawn_gltf)
+        .init_resource::<footsteps::Footsteps>()
+        .init_resource::<laser_sounds::LaserSounds>()
+        .add_startup_system(spawn_physics_cube)
         .add_startup_system(spawn_menu_camera)
         .add_system(menu::apply_gltf_extras.in_base_set(CoreSet::PreUpdate))
+        .add_system(menu::activate_menu_camera.in_schedule(OnEnter(AppState::Loading)))
         .add_system(menu::activate_menu_camera.in_schedule(OnEnter(AppState::Menu)))
         .add_system(menu::spawn_menu_screen.in_schedule(OnEnter(AppState::Menu)))
-        .add_systems((menu::create_colliders, menu::start_game).in_set(OnUpdate(AppState::Menu)))
+        .add_system(menu::create_colliders.run_if(|loaded: Res<CollidersLoaded>| !loaded.0))
+        .add_system(menu::start_game.in_set(OnUpdate(AppState::Menu)))
         .add_system(game::activate_game_camera.in_schedule(OnEnter(AppState::InGame)))
         .add_system(game::spawn_player.in_schedule(OnEnter(AppState::InGame)))
         .add_system(hud::spawn_hud.in_schedule(OnEnter(AppState::InGame)))
@@ -96,12 +133,28 @@ fn main() {
                 game::change_size,
                 game::back_to_menu,
                 game::process_triggers,
+                footsteps::footsteps,
                 post_processing::change_blur,
                 hud::update_body_icon,
                 hud::update_subtitle,
             )
                 .in_set(OnUpdate(AppState::InGame)),
         )
+        .add_systems(
+            (
+                hud::record_history,
+                hud::toggle_history,
+                hud::update_history_panel,
+                hud::choose_reply,
+                hud::update_reply_text,
+            )
+                .in_set(OnUpdate(AppState::InGame)),
+        )
+        .add_systems(
+            (laser_sounds::hum_lasers, laser_sounds::zap_player)
+                .in_set(OnUpdate(AppState::InGame)),
+        )
+        .add_system(laser_sounds::stop_laser_hum.in_schedule(OnExit(AppState::InGame)))
         .add_system(hud::despawn_hud.in_schedule(OnExit(AppState::InGame)))
         .add_system(menu::activate_menu_camera.in_schedule(OnEnter(AppState::Finish)))
         .add_system(finish::spawn_finish_screen.in_schedule(OnEnter(AppState::Finish)))
@@ -198,27 +251,17 @@ fn setup_player(In(RenderTargetImage(render_target)): In<RenderTargetImage>, mut
             Transform::from_xyz(0.0, -capsule_total_half_height - leg_down_margin, 0.0),
             Sensor,
             ActiveEvents::COLLISION_EVENTS,
+            // triggers and audio zones are no ground to stand on
+            CollisionGroups::new(Group::ALL, !SENSOR_VOLUMES),
         ));
     });
 }
 
-fn spawn_gltf(
+fn spawn_physics_cube(
     mut commands: Commands,
-    ass: Res<AssetServer>,
     mut meshes: ResMut<Assets<Mesh>>,
     mut materials: ResMut<Assets<StandardMaterial>>,
 ) {
-    // note that we have to include the `Scene0` label
-    let my_gltf = ass.load("bvj-3-level-7.glb#Scene0");
-
-    // to position our 3d model, simply use the Transform
-    // in the SceneBundle
-    commands.spawn(SceneBundle {
-        scene: my_gltf,
-        transform: Transform::from_xyz(0.0, 0.0, 0.0),
-        ..Default::default()
-    });
-
     // Physics test cube
     let cube_mesh = Mesh::from(shape::Cube { size: 0.2 });
     let collider = Collider::from_bevy_mesh(&cube_mesh, &default()).unwrap();
@@ -233,11 +276,6 @@ fn spawn_gltf(
         .insert(collider);
 }
 
-#[derive(Deserialize)]
-struct NodeMeta {
-    role: String,
-}
-
 #[derive(Resource)]
 pub struct CollidersLoaded(bool);
 
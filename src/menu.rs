@@ -1,15 +1,22 @@
-use std::str::FromStr;
-
 use bevy::{
     gltf::{GltfExtras, GltfMesh},
     prelude::*,
+    render::mesh::VertexAttributeValues,
+    scene::{SceneInstance, SceneSpawner},
     utils::HashSet,
 };
-use bevy_rapier3d::prelude::{ActiveEvents, Collider, ComputedColliderShape, RigidBody, Sensor};
+use bevy_rapier3d::prelude::{
+    ActiveEvents, Collider, CollisionGroups, ComputedColliderShape, Group, RigidBody, Sensor,
+};
 
 use crate::{
-    game::GameState, post_processing::GameCamera, AppState, CameraMenu, CollidersLoaded, NodeMeta,
-    PlayerBody, PlayerSpawn,
+    audio::LabSpeaker,
+    barks::{OffRouteZone, RouteZone},
+    extras::{collider_shape, parse_extras, ColliderShape, LoadReport, NodeRole},
+    levels::{LevelEntity, LevelScene, Prefabs},
+    locale::Localizer,
+    post_processing::GameCamera,
+    AppState, CameraMenu, CollidersLoaded, PlayerBody, PlayerSpawn,
 };
 
 pub(crate) fn activate_menu_camera(
@@ -25,10 +32,14 @@ pub(crate) fn activate_menu_camera(
 #[derive(Component)]
 pub struct MenuScreen;
 
-pub fn spawn_menu_screen(mut commands: Commands, asset_server: Res<AssetServer>) {
+pub fn spawn_menu_screen(
+    mut commands: Commands,
+    asset_server: Res<AssetServer>,
+    localizer: Localizer,
+) {
     commands.spawn((
         SpriteBundle {
-            texture: asset_server.load("screens/start_screen.png"),
+            texture: asset_server.load(localizer.text("screen.start", "screens/start_screen.png")),
             ..default()
         },
         MenuScreen,
@@ -38,35 +49,62 @@ pub fn spawn_menu_screen(mut commands: Commands, asset_server: Res<AssetServer>)
 /// Used as both
 /// - component for the sensor
 /// - event
-#[allow(non_camel_case_types)]
-#[derive(Component, Clone, strum::EnumString, PartialEq, Eq)]
+#[derive(Component, Clone, PartialEq, Eq)]
 pub enum GameTrigger {
     ExitLevel,
-    Sensor_04,
-    Speaker(Vec<String>),
-    LaserWidth,
-    LaserWidth_04,
-    LaserHeight,
-    LaserHeight_11,
-    Sensor_17,
-    Sensor_18,
-    Sensor_19,
+    /// `Sensor_<name>`, only matters for the story graph
+    Sensor(String),
+    Speaker {
+        lines: Vec<String>,
+        urgent: bool,
+    },
+    /// `LaserWidth_<group>`
+    LaserWidth(Option<String>),
+    /// `LaserHeight_<group>`
+    LaserHeight(Option<String>),
 }
 
 impl GameTrigger {
-    fn from_prop(s: &str) -> Result<Self, <Self as FromStr>::Err> {
-        if s.starts_with("Speaker") {
-            let (_, replicas) = s.split_once("_").expect("'Speaker' then underscore");
-            let replicas = replicas.split("_").map(String::from).collect();
-            Ok(GameTrigger::Speaker(replicas))
-        } else {
-            Self::from_str(s)
+    fn from_role(role: &NodeRole) -> Option<Self> {
+        Some(match role {
+            NodeRole::ExitLevel => GameTrigger::ExitLevel,
+            NodeRole::Sensor { name } => GameTrigger::Sensor(format!("Sensor_{name}")),
+            NodeRole::Speaker { lines, urgent } => GameTrigger::Speaker {
+                lines: lines.clone(),
+                urgent: *urgent,
+            },
+            NodeRole::LaserWidth { show_on } => GameTrigger::LaserWidth(show_on.clone()),
+            NodeRole::LaserHeight { show_on } => GameTrigger::LaserHeight(show_on.clone()),
+            _ => return None,
+        })
+    }
+
+    /// Role name as written in the level
+    pub fn role(&self) -> String {
+        match self {
+            GameTrigger::ExitLevel => "ExitLevel".to_string(),
+            GameTrigger::Sensor(name) => name.clone(),
+            GameTrigger::Speaker { lines, .. } => format!("Speaker_{}", lines.join("_")),
+            GameTrigger::LaserWidth(group) => laser_role("LaserWidth", group),
+            GameTrigger::LaserHeight(group) => laser_role("LaserHeight", group),
         }
     }
 }
 
+fn laser_role(kind: &str, group: &Option<String>) -> String {
+    match group {
+        Some(group) => format!("{kind}_{group}"),
+        None => kind.to_string(),
+    }
+}
+
+/// Laser group, hidden until the story shows it
+#[derive(Component)]
+pub struct ShowOn(pub String);
+
+/// Laser node, the sensor it hits the player with is a separate entity
 #[derive(Component)]
-pub struct ShowOn(pub GameState);
+pub struct Laser;
 
 /// This entity does not need further processing
 #[derive(Component)]
@@ -75,71 +113,123 @@ pub struct Processed;
 pub fn apply_gltf_extras(
     mut cmd: Commands,
     gltf_extras: Query<
-        (Entity, &GltfExtras, &Transform, &Children),
+        (Entity, &GltfExtras, &Children, Option<&Name>),
         (Without<PlayerBody>, Without<Processed>),
     >,
+    mut report: ResMut<LoadReport>,
     mut player_spawn_info: Query<&mut PlayerSpawn, With<PlayerBody>>,
     bevy_meshes: Res<Assets<Mesh>>,
     bevy_mesh_components: Query<&Handle<Mesh>>,
+    hierarchy: Query<(&Transform, Option<&Parent>)>,
+    prefabs: Prefabs,
 ) {
-    for (ent, gltf_extras, transform, ent_children) in gltf_extras.iter() {
-        let meta: NodeMeta = serde_json::from_str(&gltf_extras.value).unwrap();
-        info!("Found role {:?}", meta.role);
-
-        if let Ok(trigger) = GameTrigger::from_prop(&meta.role) {
-            for child in ent_children {
-                let Ok(mesh_handle) = bevy_mesh_components.get(*child) else {continue};
-                let mesh = bevy_meshes.get(mesh_handle).unwrap();
-                let collider = Collider::from_bevy_mesh(mesh, &default()).unwrap();
-                cmd.spawn((
-                    trigger.clone(),
-                    collider,
-                    *transform,
-                    Sensor,
-                    ActiveEvents::COLLISION_EVENTS,
-                    GlobalTransform::default(),
-                ));
+    // nodes of prefabs are not direct children of the level
+    let level_transform = |mut ent: Entity| {
+        let mut transform = Transform::IDENTITY;
+        while let Ok((local, parent)) = hierarchy.get(ent) {
+            transform = local.mul_transform(transform);
+            let Some(parent) = parent else { break };
+            ent = parent.get();
+        }
+        transform
+    };
+    for (ent, gltf_extras, ent_children, name) in gltf_extras.iter() {
+        let node = name.map_or_else(|| format!("{ent:?}"), |n| n.to_string());
+        let transform = &level_transform(ent);
+        let role = match parse_extras(&gltf_extras.value) {
+            Ok(role) => role,
+            Err(e) => {
+                report.add(&node, e);
+                cmd.entity(ent).insert(Processed);
+                continue;
             }
-            if !meta.role.starts_with("Laser") {
+        };
+        info!("Found role {:?}", role);
+        let shape = collider_shape(&gltf_extras.value).unwrap_or_else(|e| {
+            report.add(&node, e);
+            ColliderShape::default()
+        });
+        let child_colliders = |children: &Children| -> Vec<Collider> {
+            children
+                .iter()
+                .filter_map(|child| bevy_mesh_components.get(*child).ok())
+                .filter_map(|mesh| shape_collider(bevy_meshes.get(mesh).unwrap(), shape))
+                .collect()
+        };
+
+        if let Some(trigger) = GameTrigger::from_role(&role) {
+            for collider in child_colliders(ent_children) {
+                cmd.spawn((trigger.clone(), SensorVolume::new(collider, transform)));
+            }
+            if !role.is_laser() {
                 info!("Not a laser, destroying");
                 cmd.entity(ent).despawn_recursive();
             } else {
-                if meta.role.contains("_04") {
-                    cmd.entity(ent)
-                        .insert((Visibility::Hidden, ShowOn(GameState::TurnOnLaser2)));
-                } else if meta.role.contains("_11") {
-                    cmd.entity(ent)
-                        .insert((Visibility::Hidden, ShowOn(GameState::TurnOnLaser1)));
+                if let GameTrigger::LaserWidth(Some(group))
+                | GameTrigger::LaserHeight(Some(group)) = trigger
+                {
+                    cmd.entity(ent).insert((Visibility::Hidden, ShowOn(group)));
                 }
-                cmd.entity(ent).insert(Processed);
+                cmd.entity(ent).insert((Laser, Processed));
             }
-            return;
+            continue;
         }
 
-        match meta.role.as_str() {
-            "PlayerSpawn" => {
+        match role {
+            NodeRole::PlayerSpawn => {
                 player_spawn_info.single_mut().0 .0 = transform.translation;
                 cmd.entity(ent).despawn_recursive()
             }
-            "PlayerSpawnLookAt" => {
+            NodeRole::PlayerSpawnLookAt => {
                 player_spawn_info.single_mut().0 .1 = transform.translation;
                 cmd.entity(ent).despawn_recursive()
             }
-            "Collider" => {
-                for child in ent_children {
-                    let Ok(mesh_handle) = bevy_mesh_components.get(*child) else {continue};
-                    let mesh = bevy_meshes.get(mesh_handle).unwrap();
-                    let collider = Collider::from_bevy_mesh(mesh, &default()).unwrap();
+            NodeRole::Collider => {
+                for collider in child_colliders(ent_children) {
                     cmd.spawn((
                         RigidBody::Fixed,
                         collider,
                         *transform,
                         GlobalTransform::default(),
+                        LevelEntity,
                     ));
                 }
                 cmd.entity(ent).despawn_recursive()
             }
-            r => warn!("Unknown role {r}"),
+            NodeRole::AudioZone(zone) => {
+                for collider in child_colliders(ent_children) {
+                    cmd.spawn((zone.clone(), SensorVolume::new(collider, transform)));
+                }
+                cmd.entity(ent).despawn_recursive()
+            }
+            NodeRole::Route => {
+                for collider in child_colliders(ent_children) {
+                    cmd.spawn((RouteZone, SensorVolume::new(collider, transform)));
+                }
+                cmd.entity(ent).despawn_recursive()
+            }
+            NodeRole::OffRoute => {
+                for collider in child_colliders(ent_children) {
+                    cmd.spawn((OffRouteZone, SensorVolume::new(collider, transform)));
+                }
+                cmd.entity(ent).despawn_recursive()
+            }
+            NodeRole::Prefab { name } => match prefabs.spawn(&mut cmd, &name, *transform) {
+                Ok(true) => cmd.entity(ent).despawn_recursive(),
+                // the library is still loading
+                Ok(false) => {}
+                Err(e) => {
+                    report.add(&node, e);
+                    cmd.entity(ent).insert(Processed);
+                }
+            },
+            NodeRole::AudioSpeaker { channel } => {
+                cmd.entity(ent).insert((LabSpeaker(channel), Processed));
+            }
+            // triggers are handled above
+            _ => {
+                cmd.entity(ent).insert(Processed);
+            }
         }
     }
 }
@@ -147,6 +237,66 @@ pub fn apply_gltf_extras(
 #[derive(Component)]
 pub struct NoCollider;
 
+/// Collider of the chosen shape around the mesh
+fn shape_collider(mesh: &Mesh, shape: ColliderShape) -> Option<Collider> {
+    let computed = match shape {
+        ColliderShape::Trimesh => ComputedColliderShape::TriMesh,
+        ColliderShape::ConvexDecomposition => ComputedColliderShape::ConvexDecomposition(default()),
+        ColliderShape::ConvexHull => {
+            let Some(VertexAttributeValues::Float32x3(positions)) =
+                mesh.attribute(Mesh::ATTRIBUTE_POSITION)
+            else {
+                return None;
+            };
+            let points: Vec<Vec3> = positions.iter().map(|p| Vec3::from(*p)).collect();
+            return Collider::convex_hull(&points);
+        }
+        ColliderShape::Box | ColliderShape::Capsule => {
+            let aabb = mesh.compute_aabb()?;
+            let half = Vec3::from(aabb.half_extents);
+            let collider = if shape == ColliderShape::Box {
+                Collider::cuboid(half.x, half.y, half.z)
+            } else {
+                let radius = half.x.max(half.z);
+                Collider::capsule_y((half.y - radius).max(0.0), radius)
+            };
+            // the mesh is not always centered on its node
+            let center = Vec3::from(aabb.center);
+            return Some(Collider::compound(vec![(center, Quat::IDENTITY, collider)]));
+        }
+    };
+    Collider::from_bevy_mesh(mesh, &computed)
+}
+
+/// Collision group of the sensor volumes, the player legs do not touch it
+pub const SENSOR_VOLUMES: Group = Group::GROUP_2;
+
+/// Volume of the level the player can be inside of
+#[derive(Bundle)]
+struct SensorVolume {
+    collider: Collider,
+    transform: Transform,
+    global_transform: GlobalTransform,
+    sensor: Sensor,
+    events: ActiveEvents,
+    groups: CollisionGroups,
+    level: LevelEntity,
+}
+
+impl SensorVolume {
+    fn new(collider: Collider, transform: &Transform) -> Self {
+        SensorVolume {
+            collider,
+            transform: *transform,
+            global_transform: GlobalTransform::default(),
+            sensor: Sensor,
+            events: ActiveEvents::COLLISION_EVENTS,
+            groups: CollisionGroups::new(SENSOR_VOLUMES, Group::ALL),
+            level: LevelEntity,
+        }
+    }
+}
+
 pub fn create_colliders(
     mut cmd: Commands,
     mut loaded: ResMut<CollidersLoaded>,
@@ -157,14 +307,16 @@ pub fn create_colliders(
         (Without<Collider>, Without<NoCollider>),
     >,
     extras: Query<&GltfExtras>,
+    scene_spawner: Res<SceneSpawner>,
+    level: Query<(), With<LevelScene>>,
+    scenes: Query<Option<&SceneInstance>, With<Handle<Scene>>>,
+    unprocessed: Query<(), (With<GltfExtras>, With<Children>, Without<Processed>)>,
 ) {
-    // FIXME: change into a system set run condition
-    if loaded.0 {
-        return;
-    }
-
-    if gltf_meshes.iter().next().is_none() {
-        // so we do not mark as loaded before we have any meshes
+    // so we do not mark as loaded before the level and prefab meshes are there
+    let ready = |scene: Option<&SceneInstance>| {
+        scene.is_some_and(|scene| scene_spawner.instance_is_ready(**scene))
+    };
+    if level.is_empty() || !scenes.iter().all(ready) || !unprocessed.is_empty() {
         return;
     }
 
@@ -177,17 +329,22 @@ pub fn create_colliders(
     let mut colliders = 0;
 
     for (ent, mesh_id, parent) in entities_with_meshes.iter() {
+        let mut shape = ColliderShape::default();
         if let Ok(extras) = extras.get(parent.get()) {
-            let meta: NodeMeta = serde_json::from_str(&extras.value).unwrap();
-            if meta.role.starts_with("Laser") {
+            if parse_extras(&extras.value).is_ok_and(|role| role.is_laser()) {
                 cmd.entity(ent).insert(NoCollider);
                 continue;
             }
+            // broken shapes are reported by `apply_gltf_extras`
+            shape = collider_shape(&extras.value).unwrap_or_default();
         }
         if meshes_came_from_gltf.contains(mesh_id) {
             let mesh = bevy_meshes.get(mesh_id).unwrap();
-            let collider =
-                Collider::from_bevy_mesh(mesh, &ComputedColliderShape::default()).unwrap();
+            let Some(collider) = shape_collider(mesh, shape) else {
+                warn!("Could not make a {shape:?} collider");
+                cmd.entity(ent).insert(NoCollider);
+                continue;
+            };
             cmd.entity(ent).insert(RigidBody::Fixed).insert(collider);
             colliders += 1;
         }
@@ -206,3 +363,27 @@ pub fn start_game(keyboard: Res<Input<KeyCode>>, mut next_state: ResMut<NextStat
         next_state.set(AppState::InGame);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn trigger_role_round_trip() {
+        for role in [
+            "ExitLevel",
+            "Sensor_04",
+            "Speaker_01_02_03",
+            "LaserWidth",
+            "LaserWidth_04",
+            "LaserHeight",
+            "LaserHeight_11",
+        ] {
+            let node = parse_extras(&format!(r#"{{"role": "{role}"}}"#)).unwrap();
+            let trigger = GameTrigger::from_role(&node).unwrap();
+            assert_eq!(trigger.role(), role);
+            // what `validate_level` checks the story against
+            assert_eq!(node.trigger().as_deref(), Some(role));
+        }
+    }
+}
@@ -0,0 +1,174 @@
+use bevy::prelude::*;
+
+use crate::{
+    locale::Localizer,
+    settings::{Mixer, Settings},
+    AppState,
+};
+
+pub(crate) struct MixerPlugin;
+
+impl Plugin for MixerPlugin {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<MixerSelection>()
+            .add_system(spawn_mixer_panel.in_schedule(OnEnter(AppState::Menu)))
+            .add_systems((adjust_mixer, update_mixer_panel).in_set(OnUpdate(AppState::Menu)))
+            .add_system(despawn_mixer_panel.in_schedule(OnExit(AppState::Menu)));
+    }
+}
+
+#[derive(Clone, Copy)]
+enum MixerRow {
+    Master,
+    Music,
+    Voice,
+    Sfx,
+    DialogueSpeed,
+}
+
+static ROWS: [MixerRow; 5] = [
+    MixerRow::Master,
+    MixerRow::Music,
+    MixerRow::Voice,
+    MixerRow::Sfx,
+    MixerRow::DialogueSpeed,
+];
+
+impl MixerRow {
+    /// Locale key and English name
+    fn label(self) -> (&'static str, &'static str) {
+        match self {
+            MixerRow::Master => ("mixer.master", "Master"),
+            MixerRow::Music => ("mixer.music", "Music"),
+            MixerRow::Voice => ("mixer.voice", "Voices"),
+            MixerRow::Sfx => ("mixer.sfx", "Effects"),
+            MixerRow::DialogueSpeed => ("mixer.dialogue_speed", "Dialogue speed"),
+        }
+    }
+
+    fn value(self, mixer: &Mixer) -> f32 {
+        match self {
+            MixerRow::Master => mixer.master,
+            MixerRow::Music => mixer.music,
+            MixerRow::Voice => mixer.voice,
+            MixerRow::Sfx => mixer.sfx,
+            MixerRow::DialogueSpeed => mixer.dialogue_speed,
+        }
+    }
+
+    fn value_mut(self, mixer: &mut Mixer) -> &mut f32 {
+        match self {
+            MixerRow::Master => &mut mixer.master,
+            MixerRow::Music => &mut mixer.music,
+            MixerRow::Voice => &mut mixer.voice,
+            MixerRow::Sfx => &mut mixer.sfx,
+            MixerRow::DialogueSpeed => &mut mixer.dialogue_speed,
+        }
+    }
+
+    fn range(self) -> (f32, f32) {
+        match self {
+            MixerRow::DialogueSpeed => (0.5, 2.0),
+            _ => (0.0, 1.0),
+        }
+    }
+
+    fn format(self, value: f32) -> String {
+        match self {
+            MixerRow::DialogueSpeed => format!("{value:.1}x"),
+            _ => format!("{:.0}%", value * 100.0),
+        }
+    }
+}
+
+static MIXER_STEP: f32 = 0.1;
+
+#[derive(Resource, Default)]
+struct MixerSelection(usize);
+
+#[derive(Component)]
+struct MixerPanel;
+
+fn spawn_mixer_panel(mut commands: Commands, localizer: Localizer) {
+    commands.spawn((
+        TextBundle::from_section(
+            "",
+            TextStyle {
+                font: localizer.font(),
+                font_size: 10.0,
+                color: Color::WHITE,
+            },
+        )
+        .with_style(Style {
+            position_type: PositionType::Absolute,
+            position: UiRect {
+                left: Val::Px(20.0),
+                bottom: Val::Px(20.0),
+                ..default()
+            },
+            ..default()
+        }),
+        MixerPanel,
+    ));
+}
+
+fn despawn_mixer_panel(mut commands: Commands, panel: Query<Entity, With<MixerPanel>>) {
+    for panel in panel.iter() {
+        commands.entity(panel).despawn_recursive();
+    }
+}
+
+/// Up and down pick a level, left and right change it
+fn adjust_mixer(
+    keyboard: Res<Input<KeyCode>>,
+    mut selection: ResMut<MixerSelection>,
+    mut settings: ResMut<Settings>,
+) {
+    if keyboard.just_pressed(KeyCode::Up) {
+        selection.0 = (selection.0 + ROWS.len() - 1) % ROWS.len();
+    }
+    if keyboard.just_pressed(KeyCode::Down) {
+        selection.0 = (selection.0 + 1) % ROWS.len();
+    }
+    let step = match (
+        keyboard.just_pressed(KeyCode::Left),
+        keyboard.just_pressed(KeyCode::Right),
+    ) {
+        (true, false) => -MIXER_STEP,
+        (false, true) => MIXER_STEP,
+        _ => return,
+    };
+    let row = ROWS[selection.0];
+    let (min, max) = row.range();
+    let value = row.value_mut(&mut settings.mixer);
+    *value = (((*value + step) / MIXER_STEP).round() * MIXER_STEP).clamp(min, max);
+    settings.save();
+}
+
+fn update_mixer_panel(
+    selection: Res<MixerSelection>,
+    settings: Res<Settings>,
+    localizer: Localizer,
+    mut panel: Query<&mut Text, With<MixerPanel>>,
+) {
+    let Ok(mut text) = panel.get_single_mut() else {
+        return;
+    };
+    let style = text.sections[0].style.clone();
+    text.sections = ROWS
+        .iter()
+        .enumerate()
+        .map(|(n, row)| {
+            let (key, default) = row.label();
+            let cursor = if n == selection.0 { ">" } else { " " };
+            TextSection::new(
+                format!(
+                    "{cursor} {}: {}\n",
+                    localizer.text(key, default),
+                    row.format(row.value(&settings.mixer))
+                ),
+                style.clone(),
+            )
+        })
+        .collect();
+}
@@ -0,0 +1,210 @@
+use bevy::prelude::*;
+use bevy_kira_audio::{AudioApp, AudioChannel, AudioControl, AudioInstance, AudioTween};
+use serde::Deserialize;
+
+use crate::{
+    audio::VoiceChannels,
+    settings::Settings,
+    story::{GameState, StoryGraph, StoryGraphHandle},
+};
+
+pub(crate) struct MusicPlugin;
+
+impl Plugin for MusicPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_audio_channel::<BgMusic>()
+            .add_audio_channel::<Stingers>()
+            .init_resource::<MusicDirector>()
+            .add_systems((duck_music, direct_music).chain());
+    }
+}
+
+#[derive(Resource)]
+struct BgMusic;
+
+/// One-shot accents played over the music
+#[derive(Resource)]
+struct Stingers;
+
+static MUSIC_VOLUME: f64 = 0.2;
+static STINGER_VOLUME: f64 = 0.5;
+/// Seconds for one track to fade out while the next one fades in
+pub static CROSSFADE_TIME: f32 = 3.0;
+
+/// Looped track of a story state
+#[derive(Deserialize, PartialEq, Clone, Debug)]
+pub struct MusicCue {
+    /// Path inside `assets/`
+    pub track: String,
+    /// Playing a track faster makes it more tense
+    #[serde(default = "normal_rate")]
+    pub rate: f64,
+    /// Looped over the track, such as a tense layer. Changing only the layer keeps the track going
+    #[serde(default)]
+    pub layer: Option<String>,
+}
+
+fn normal_rate() -> f64 {
+    1.0
+}
+
+struct FadingTrack<K> {
+    key: K,
+    instance: Handle<AudioInstance>,
+    /// 0 is silent, 1 is full volume
+    fade: f32,
+}
+
+/// Looped tracks fading into each other
+pub struct Crossfader<K> {
+    /// The last one is fading in, the rest are fading out
+    tracks: Vec<FadingTrack<K>>,
+}
+
+impl<K> Default for Crossfader<K> {
+    fn default() -> Self {
+        Crossfader { tracks: vec![] }
+    }
+}
+
+impl<K: PartialEq + Clone> Crossfader<K> {
+    /// Fades to the track of `target`, or to silence, by `step` each call.
+    /// `play` starts the track with zero volume unless it is still fading out
+    pub fn update(
+        &mut self,
+        target: Option<&K>,
+        play: impl FnOnce(&K) -> Handle<AudioInstance>,
+        step: f32,
+        volume: impl Fn(&K) -> f64,
+        audio_instances: &mut Assets<AudioInstance>,
+    ) {
+        if let Some(key) = target.filter(|k| Some(*k) != self.tracks.last().map(|t| &t.key)) {
+            let track = match self.tracks.iter().position(|t| t.key == *key) {
+                Some(n) => self.tracks.remove(n),
+                None => FadingTrack {
+                    key: key.clone(),
+                    instance: play(key),
+                    fade: 0.0,
+                },
+            };
+            self.tracks.push(track);
+        }
+
+        self.tracks.retain_mut(|track| {
+            track.fade = if Some(&track.key) == target {
+                (track.fade + step).min(1.0)
+            } else {
+                (track.fade - step).max(0.0)
+            };
+            let Some(instance) = audio_instances.get_mut(&track.instance) else {
+                // not started yet
+                return true;
+            };
+            if track.fade == 0.0 {
+                instance.stop(AudioTween::default());
+                return false;
+            }
+            instance.set_volume(
+                volume(&track.key) * track.fade as f64,
+                AudioTween::default(),
+            );
+            true
+        });
+    }
+}
+
+/// Switches music with the story state
+#[derive(Resource, Default)]
+pub struct MusicDirector {
+    tracks: Crossfader<MusicCue>,
+    layers: Crossfader<String>,
+    story_state: String,
+    /// 0 is full volume, 1 is ducked as deep as the settings allow
+    duck: f32,
+}
+
+/// Lowers the music while a voice is playing so it does not mask the words
+fn duck_music(
+    time: Res<Time>,
+    settings: Res<Settings>,
+    voices: VoiceChannels,
+    mut director: ResMut<MusicDirector>,
+) {
+    let ducking = &settings.ducking;
+    let (target, seconds) = if voices.is_playing() {
+        (1.0, ducking.attack)
+    } else {
+        (0.0, ducking.release)
+    };
+    let step = time.delta_seconds() / seconds.max(f32::EPSILON);
+    director.duck = if director.duck < target {
+        (director.duck + step).min(target)
+    } else {
+        (director.duck - step).max(target)
+    };
+}
+
+fn direct_music(
+    time: Res<Time>,
+    asset_server: Res<AssetServer>,
+    settings: Res<Settings>,
+    mut director: ResMut<MusicDirector>,
+    mut audio_instances: ResMut<Assets<AudioInstance>>,
+    music: Res<AudioChannel<BgMusic>>,
+    stingers: Res<AudioChannel<Stingers>>,
+    game_state: Res<GameState>,
+    story: Res<StoryGraphHandle>,
+    stories: Res<Assets<StoryGraph>>,
+) {
+    let Some(story) = stories.get(&story.0) else {
+        return;
+    };
+
+    if director.story_state != game_state.0 {
+        director.story_state = game_state.0.clone();
+        if let Some(stinger) = story.state(&game_state.0).and_then(|s| s.stinger.as_ref()) {
+            stingers
+                .play(asset_server.load(stinger.as_str()))
+                .with_volume(STINGER_VOLUME * settings.mixer.music());
+        }
+    }
+
+    let cue = story.music(&game_state);
+    let volume = MUSIC_VOLUME
+        * settings.mixer.music()
+        * (1.0 - settings.ducking.depth * director.duck) as f64;
+    let step = time.delta_seconds() / CROSSFADE_TIME;
+    let track = cue.map(|cue| MusicCue {
+        layer: None,
+        ..cue.clone()
+    });
+    director.tracks.update(
+        track.as_ref(),
+        |cue| {
+            info!("Music {cue:?}");
+            music
+                .play(asset_server.load(cue.track.as_str()))
+                .looped()
+                .with_volume(0.0)
+                .with_playback_rate(cue.rate)
+                .handle()
+        },
+        step,
+        |_| volume,
+        &mut audio_instances,
+    );
+    director.layers.update(
+        cue.and_then(|cue| cue.layer.as_ref()),
+        |layer| {
+            info!("Music layer {layer}");
+            music
+                .play(asset_server.load(layer.as_str()))
+                .looped()
+                .with_volume(0.0)
+                .handle()
+        },
+        step,
+        |_| volume,
+        &mut audio_instances,
+    );
+}
@@ -0,0 +1,96 @@
+use bevy::prelude::*;
+use serde::{Deserialize, Serialize};
+
+static SETTINGS_FILE: &str = "settings.json";
+
+/// Player preferences kept in `settings.json` next to the game
+#[derive(Resource, Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Settings {
+    /// Language code of a table in `assets/locale/`, `--lang <code>` overrides it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    /// Show subtitles only, each line stays for its reading time
+    pub muted_voices: bool,
+    pub ducking: DuckingSettings,
+    pub mixer: Mixer,
+}
+
+/// How the music gets out of the way of voices
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DuckingSettings {
+    /// Part of the music volume taken away, 0 turns ducking off
+    pub depth: f32,
+    /// Seconds to duck when a voice starts
+    pub attack: f32,
+    /// Seconds to come back after the voice ends
+    pub release: f32,
+}
+
+impl Default for DuckingSettings {
+    fn default() -> Self {
+        DuckingSettings {
+            depth: 0.6,
+            attack: 0.3,
+            release: 1.5,
+        }
+    }
+}
+
+/// Volume levels from 0 to 1, the master level scales all the others
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct Mixer {
+    pub master: f32,
+    pub music: f32,
+    pub voice: f32,
+    pub sfx: f32,
+    /// Playback rate of the voice lines
+    pub dialogue_speed: f32,
+}
+
+impl Default for Mixer {
+    fn default() -> Self {
+        Mixer {
+            master: 1.0,
+            music: 1.0,
+            voice: 1.0,
+            sfx: 1.0,
+            dialogue_speed: 1.5,
+        }
+    }
+}
+
+impl Mixer {
+    pub fn music(&self) -> f64 {
+        (self.master * self.music) as f64
+    }
+
+    pub fn voice(&self) -> f64 {
+        (self.master * self.voice) as f64
+    }
+
+    pub fn sfx(&self) -> f64 {
+        (self.master * self.sfx) as f64
+    }
+}
+
+impl Settings {
+    pub fn load() -> Self {
+        let Ok(json) = std::fs::read_to_string(SETTINGS_FILE) else {
+            return Settings::default();
+        };
+        serde_json::from_str(&json).unwrap_or_else(|e| {
+            warn!("Ignoring broken {SETTINGS_FILE}: {e}");
+            Settings::default()
+        })
+    }
+
+    pub fn save(&self) {
+        let json = serde_json::to_string_pretty(self).expect("settings are always serializable");
+        if let Err(e) = std::fs::write(SETTINGS_FILE, json) {
+            warn!("Could not save {SETTINGS_FILE}: {e}");
+        }
+    }
+}
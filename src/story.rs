@@ -0,0 +1,175 @@
+use bevy::{prelude::*, reflect::TypeUuid, utils::HashSet};
+use serde::Deserialize;
+
+use crate::{
+    game::LaserTrigger,
+    json_asset::{JsonAsset, JsonLoader},
+    menu::GameTrigger,
+    music::MusicCue,
+    AppState,
+};
+
+/// Name of the story state the player is in, see `assets/story/main.story.json`
+#[derive(Resource, Default, PartialEq, Eq, Clone, Debug)]
+pub struct GameState(pub String);
+
+#[derive(Deserialize, TypeUuid)]
+#[uuid = "0e5a7d1c-8f0b-4a62-b1f4-3c9d2e6a7b10"]
+pub struct StoryGraph {
+    pub initial: String,
+    /// Music of the states that do not set their own
+    pub music: Option<MusicCue>,
+    pub states: Vec<StoryState>,
+}
+
+#[derive(Deserialize)]
+pub struct StoryState {
+    pub name: String,
+    /// Dialogue phase that plays in this state
+    pub dialogue: Option<String>,
+    pub music: Option<MusicCue>,
+    /// Played once when the story enters this state
+    pub stinger: Option<String>,
+    /// Lasers that affect the player in this state
+    #[serde(default)]
+    pub armed_lasers: Vec<LaserTrigger>,
+    #[serde(default)]
+    pub on_enter: Vec<StoryAction>,
+    #[serde(default)]
+    pub transitions: Vec<StoryTransition>,
+}
+
+#[derive(Deserialize)]
+pub struct StoryTransition {
+    pub on: StoryEvent,
+    pub to: String,
+    /// Only taken if this story flag is set
+    pub if_flag: Option<String>,
+}
+
+#[derive(Deserialize, PartialEq, Eq, Clone, Debug)]
+pub enum StoryEvent {
+    /// Player entered a sensor with this role
+    Trigger(String),
+    /// Last line of this dialogue phase finished playing
+    DialogueFinished(String),
+}
+
+#[derive(Deserialize, Clone, Debug)]
+pub enum StoryAction {
+    /// Show lasers of the group, e.g. `LaserHeight_11` is in group `11`
+    ShowLasers(String),
+}
+
+impl StoryGraph {
+    pub fn state(&self, name: &str) -> Option<&StoryState> {
+        self.states.iter().find(|s| s.name == name)
+    }
+
+    pub fn dialogue_phase(&self, state: &GameState) -> Option<&str> {
+        self.state(&state.0)?.dialogue.as_deref()
+    }
+
+    pub fn music(&self, state: &GameState) -> Option<&MusicCue> {
+        self.state(&state.0)
+            .and_then(|s| s.music.as_ref())
+            .or(self.music.as_ref())
+    }
+
+    pub fn is_armed(&self, state: &GameState, laser: &LaserTrigger) -> bool {
+        self.state(&state.0)
+            .is_some_and(|s| s.armed_lasers.contains(laser))
+    }
+}
+
+#[derive(Resource)]
+pub struct StoryGraphHandle(pub Handle<StoryGraph>);
+
+impl FromWorld for StoryGraphHandle {
+    fn from_world(world: &mut World) -> Self {
+        let asset_server = world.resource::<AssetServer>();
+        StoryGraphHandle(asset_server.load("story/main.story.json"))
+    }
+}
+
+/// Set by the replies the player picks, see `ReplyChoice`
+#[derive(Resource, Default)]
+pub struct StoryFlags(pub HashSet<String>);
+
+/// Laser groups made visible by [`StoryAction::ShowLasers`]
+#[derive(Resource, Default)]
+pub struct ShownLasers(pub HashSet<String>);
+
+pub(crate) struct StoryPlugin;
+
+impl Plugin for StoryPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_asset::<StoryGraph>()
+            .init_asset_loader::<JsonLoader<StoryGraph>>()
+            .init_resource::<StoryGraphHandle>()
+            .init_resource::<GameState>()
+            .init_resource::<ShownLasers>()
+            .init_resource::<StoryFlags>()
+            .add_event::<StoryEvent>()
+            .add_system(
+                advance_story
+                    .before(crate::game::process_triggers)
+                    .in_set(OnUpdate(AppState::InGame)),
+            );
+    }
+}
+
+fn advance_story(
+    mut game_state: ResMut<GameState>,
+    mut triggers: EventReader<GameTrigger>,
+    mut story_events: EventReader<StoryEvent>,
+    mut shown_lasers: ResMut<ShownLasers>,
+    flags: Res<StoryFlags>,
+    graph: Res<StoryGraphHandle>,
+    graphs: Res<Assets<StoryGraph>>,
+) {
+    let Some(graph) = graphs.get(&graph.0) else {
+        return;
+    };
+
+    let mut enter = |next: &str, game_state: &mut GameState| {
+        info!("Story state {:?} -> {next:?}", game_state.0);
+        let Some(state) = graph.state(next) else {
+            warn!("Unknown story state {next}");
+            return;
+        };
+        for action in &state.on_enter {
+            match action {
+                StoryAction::ShowLasers(group) => {
+                    shown_lasers.0.insert(group.clone());
+                }
+            }
+        }
+        game_state.0 = next.to_string();
+    };
+
+    if game_state.0.is_empty() {
+        enter(&graph.initial, &mut game_state);
+    }
+
+    let events = triggers
+        .iter()
+        .map(|t| StoryEvent::Trigger(t.role()))
+        .chain(story_events.iter().cloned());
+    for event in events {
+        let Some(state) = graph.state(&game_state.0) else {
+            continue;
+        };
+        let taken = state
+            .transitions
+            .iter()
+            .find(|t| t.on == event && t.if_flag.as_ref().is_none_or(|f| flags.0.contains(f)));
+        if let Some(t) = taken {
+            enter(&t.to, &mut game_state);
+        }
+    }
+}
+
+impl JsonAsset for StoryGraph {
+    const EXTENSIONS: &'static [&'static str] = &["story.json"];
+}
@@ -0,0 +1,82 @@
+use std::marker::PhantomData;
+
+use bevy::prelude::*;
+use bevy_rapier3d::prelude::CollisionEvent;
+
+use crate::{AppState, PlayerBody};
+
+/// Keeps track of the `Z` volumes of the level the player is inside of
+pub(crate) struct ZoneTrackingPlugin<Z>(PhantomData<Z>);
+
+impl<Z> Default for ZoneTrackingPlugin<Z> {
+    fn default() -> Self {
+        ZoneTrackingPlugin(PhantomData)
+    }
+}
+
+impl<Z: Component> Plugin for ZoneTrackingPlugin<Z> {
+    fn build(&self, app: &mut App) {
+        app.init_resource::<InsideZones<Z>>()
+            .add_system(
+                track_zones::<Z>
+                    .in_set(ZoneTracking)
+                    .in_set(OnUpdate(AppState::InGame)),
+            )
+            .add_system(leave_zones::<Z>.in_schedule(OnExit(AppState::InGame)));
+    }
+}
+
+/// Systems reading [`InsideZones`] run after this
+#[derive(SystemSet, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ZoneTracking;
+
+#[derive(Resource)]
+pub struct InsideZones<Z> {
+    /// In the order they were entered
+    inside: Vec<Entity>,
+    zone: PhantomData<Z>,
+}
+
+impl<Z> Default for InsideZones<Z> {
+    fn default() -> Self {
+        InsideZones {
+            inside: Vec::new(),
+            zone: PhantomData,
+        }
+    }
+}
+
+impl<Z> InsideZones<Z> {
+    pub fn is_empty(&self) -> bool {
+        self.inside.is_empty()
+    }
+
+    /// Zone entered last the player is still inside of
+    pub fn last(&self) -> Option<Entity> {
+        self.inside.last().copied()
+    }
+}
+
+fn track_zones<Z: Component>(
+    mut collision_events: EventReader<CollisionEvent>,
+    player: Query<Entity, With<PlayerBody>>,
+    zones: Query<(), With<Z>>,
+    mut inside: ResMut<InsideZones<Z>>,
+) {
+    let player = player.single();
+    for event in collision_events.iter() {
+        let (CollisionEvent::Started(e1, e2, _) | CollisionEvent::Stopped(e1, e2, _)) = event;
+        let zone = match (*e1, *e2) {
+            (p, zone) | (zone, p) if p == player && zones.contains(zone) => zone,
+            _ => continue,
+        };
+        inside.inside.retain(|z| *z != zone);
+        if matches!(event, CollisionEvent::Started(..)) {
+            inside.inside.push(zone);
+        }
+    }
+}
+
+fn leave_zones<Z: Component>(mut inside: ResMut<InsideZones<Z>>) {
+    inside.inside.clear();
+}